@@ -1,14 +1,73 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrackerInformation {
-    pub key: String,
     pub id: String,
+    pub key: String,
+    /// Unknown while the key has not been looked up in Jira, e.g. for trackers created offline
+    pub issue_id: Option<String>,
+    /// Why the key cannot be looked up in Jira, e.g. because the issue does not exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue_error: Option<String>,
     pub description: Option<String>,
     #[serde(with = "humantime_serde")]
     pub duration: Duration,
     pub running: bool,
     pub start_time: DateTime<Local>,
+    pub first_start: Option<DateTime<Local>>,
+    pub last_start: Option<DateTime<Local>>,
+    /// Time spans the tracker was running, including the currently running one up to now
+    pub segments: Vec<Segment>,
+    /// Tracked seconds per day which have already been submitted
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub submitted_seconds: BTreeMap<NaiveDate, u64>,
+    #[serde(default)]
+    pub tempo: TempoAttributes,
+    /// The account the tracker is booked with, chosen by its project if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+/// Additional values which are sent to Tempo along with the worklogs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TempoAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Work attribute values by their attribute key
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billable: Option<bool>,
+}
+
+impl TempoAttributes {
+    pub fn is_empty(&self) -> bool {
+        self.account.is_none() && self.attributes.is_empty() && self.billable.is_none()
+    }
+
+    /// Fills values which are not set with the given defaults
+    pub fn with_defaults(&self, defaults: &TempoAttributes) -> TempoAttributes {
+        let mut attributes = defaults.attributes.clone();
+        attributes.extend(self.attributes.clone());
+        TempoAttributes {
+            account: self.account.clone().or_else(|| defaults.account.clone()),
+            attributes,
+            billable: self.billable.or(defaults.billable),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+impl Segment {
+    pub fn duration(&self) -> Duration {
+        (self.end - self.start).to_std().unwrap_or_default()
+    }
 }
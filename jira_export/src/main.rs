@@ -1,9 +1,9 @@
-use std::fs;
+use std::{env, fs};
 
 use futures::future::try_join_all;
 use serde_json::{to_string, Value};
 
-const URL: &str = "https://anevis.atlassian.net/rest/api/latest/search";
+const DEFAULT_JIRA_URL: &str = "https://anevis.atlassian.net";
 const PAGE_SIZE: usize = 100;
 
 fn read_file(path: &str) -> String {
@@ -15,14 +15,26 @@ fn read_file(path: &str) -> String {
 
 #[tokio::main]
 async fn main() {
-    let user = &read_file(".jira/email");
+    let jira_url = env::var("JIRA_URL").unwrap_or_else(|_| DEFAULT_JIRA_URL.to_string());
+    // Data Center authenticates with a personal access token only
+    let data_center = env::var("JIRA_DEPLOYMENT").is_ok_and(|d| d == "data_center");
+    let api_version = if data_center { "2" } else { "latest" };
+    let url = &format!(
+        "{}/rest/api/{}/search",
+        jira_url.trim_end_matches('/'),
+        api_version
+    );
+    let user = &(!data_center).then(|| read_file(".jira/email"));
     let token = &read_file(".jira/token");
     let client = reqwest::Client::builder().build().unwrap();
 
     let request = |params: Vec<(&str, &str)>| {
-        let url = reqwest::Url::parse_with_params(URL, params).unwrap();
+        let url = reqwest::Url::parse_with_params(url, params).unwrap();
         async {
-            let builder = client.get(url).basic_auth(user, Some(token));
+            let builder = match user {
+                Some(user) => client.get(url).basic_auth(user, Some(token)),
+                None => client.get(url).bearer_auth(token),
+            };
             let response = builder.send().await.unwrap();
             response.json::<Value>().await
         }
@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use futures::future::join_all;
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use domain::TrackerInformation;
+
+use crate::app_data::{AppData, TrackerError};
+use crate::config::{AccountConfig, AppConfig, Backend};
+use crate::jira_api::{JiraApi, JiraWorklogs};
+use crate::tempo_api::TempoApi;
+use crate::worklog::Worklog;
+use crate::worklog_sink::{BookedWorklog, SinkError, WorklogSink, WorklogSinks};
+
+/// Name of the account configured by the top level credentials
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+const INITIAL_RESOLVE_DELAY: Duration = Duration::from_secs(5);
+const MAXIMUM_RESOLVE_DELAY: Duration = Duration::from_secs(300);
+/// How often the issue ids of trackers created offline are looked up
+const ISSUE_RESOLVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The Jira account id of the user, which is unknown until Jira has been reachable once
+#[derive(Debug, Clone)]
+pub struct AccountId {
+    account: String,
+    id: Arc<RwLock<Option<String>>>,
+}
+
+impl AccountId {
+    fn new(account: &str, id: Option<String>) -> Self {
+        Self {
+            account: account.to_string(),
+            id: Arc::new(RwLock::new(id)),
+        }
+    }
+
+    pub fn get(&self) -> Result<String, SinkError> {
+        self.id
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| SinkError::Unavailable(self.account.clone()))
+    }
+
+    /// Replaces the id, returning whether it changed
+    fn set(&self, id: String) -> bool {
+        let mut current = self.id.write().unwrap();
+        let changed = current.as_ref() != Some(&id);
+        *current = Some(id);
+        changed
+    }
+}
+
+/// The clients of a single Jira site and Tempo instance
+pub struct Account {
+    pub jira_api: Arc<JiraApi>,
+    pub sinks: WorklogSinks,
+    account_id: AccountId,
+}
+
+impl Account {
+    fn new(config: &AppConfig, account: &AccountConfig, account_id: AccountId) -> Self {
+        let jira_api: Arc<JiraApi> = Arc::new((config, account).into());
+        let tempo_api: Arc<TempoApi> = Arc::new((config, account, account_id.clone()).into());
+        let jira_worklogs = Arc::new(JiraWorklogs::new(jira_api.clone(), account_id.clone()));
+        Self {
+            jira_api,
+            sinks: WorklogSinks::new(tempo_api, jira_worklogs, config),
+            account_id,
+        }
+    }
+
+    /// Fetches the account id until Jira is reachable and caches it in the state file, replacing
+    /// the cached id if it is outdated. Gives up on failures retrying cannot fix, e.g. an invalid
+    /// URL or rejected credentials.
+    async fn resolve(&self, data: &AppData) {
+        let name = &self.account_id.account;
+        let mut delay = INITIAL_RESOLVE_DELAY;
+        loop {
+            match self.jira_api.get_account_id().await {
+                Ok(id) => {
+                    data.set_account_id(name, &id);
+                    if self.account_id.set(id) {
+                        tracing::info!("resolved the Jira account id of account {}", name);
+                    }
+                    return;
+                }
+                Err(e) if !e.is_transient() => {
+                    tracing::error!(
+                        "cannot resolve the Jira account id of account {}: {}",
+                        name,
+                        e
+                    );
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "could not resolve the Jira account id of account {}, retrying in {:?}: {}",
+                        name,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAXIMUM_RESOLVE_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// A booked worklog together with the account it is booked with
+#[derive(Debug, Serialize)]
+pub struct AccountWorklog {
+    pub account: String,
+    #[serde(flatten)]
+    pub worklog: BookedWorklog,
+}
+
+/// An account whose worklogs could not be fetched
+#[derive(Debug, Serialize)]
+pub struct AccountError {
+    pub account: String,
+    pub error: String,
+}
+
+/// The booked worklogs of all accounts which could be fetched
+#[derive(Debug, Default, Serialize)]
+pub struct AccountWorklogs {
+    pub worklogs: Vec<AccountWorklog>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<AccountError>,
+}
+
+/// All configured accounts and which trackers belong to which account
+pub struct Accounts {
+    accounts: IndexMap<String, Account>,
+    projects: HashMap<String, String>,
+}
+
+impl Accounts {
+    /// Account ids are resolved by [Accounts::resolve_account_ids], until then the cached ids
+    /// are used
+    pub fn new(config: &AppConfig, data: &AppData) -> Self {
+        let configs = Some((DEFAULT_ACCOUNT, &config.default_account))
+            .into_iter()
+            .chain(
+                config
+                    .accounts
+                    .iter()
+                    .map(|(name, account)| (name.as_str(), account)),
+            );
+
+        let mut accounts = IndexMap::new();
+        let mut projects = HashMap::new();
+        for (name, account_config) in configs {
+            for project in &account_config.projects {
+                projects.insert(project.clone(), name.to_string());
+            }
+            let account_id = AccountId::new(name, data.account_id(name));
+            let account = Account::new(config, account_config, account_id);
+            accounts.insert(name.to_string(), account);
+        }
+        Self { accounts, projects }
+    }
+
+    /// Resolves the account ids of all accounts, retrying while Jira is not reachable
+    pub async fn resolve_account_ids(self: Arc<Self>, data: Arc<AppData>) {
+        join_all(self.accounts.values().map(|account| account.resolve(&data))).await;
+    }
+
+    /// Looks up the issue ids of trackers which were created while Jira was not reachable
+    pub async fn resolve_issue_ids(self: Arc<Self>, data: Arc<AppData>) {
+        loop {
+            let unresolved = data
+                .list_trackers()
+                .into_iter()
+                .filter(|tracker| tracker.issue_id.is_none() && tracker.issue_error.is_none());
+            // trackers of other accounts are still resolved if one account is not reachable
+            let mut unreachable = HashSet::new();
+            for tracker in unresolved {
+                let name = self.tracker_account(&tracker);
+                if unreachable.contains(&name) {
+                    continue;
+                }
+                let Ok(account) = self.get(&name) else {
+                    continue;
+                };
+                match account.jira_api.get_issue_info(&tracker.key).await {
+                    Ok(issue) => {
+                        tracing::info!("resolved issue {} of tracker {}", tracker.key, tracker.id);
+                        let _ = data.set_issue_id(&tracker.id, &issue.id);
+                    }
+                    Err(e) if e.is_transient() => {
+                        tracing::debug!("could not resolve issue {}: {}", tracker.key, e);
+                        unreachable.insert(name);
+                    }
+                    Err(e) => {
+                        // a missing or inaccessible issue is not retried
+                        let error = match TrackerError::from(e) {
+                            TrackerError::NotFoundError => {
+                                format!("issue {} does not exist in Jira", tracker.key)
+                            }
+                            error @ TrackerError::UpstreamForbiddenError(_) => {
+                                format!("issue {}: {}", tracker.key, error)
+                            }
+                            error => {
+                                tracing::warn!(
+                                    "issue {} cannot be resolved: {}",
+                                    tracker.key,
+                                    error
+                                );
+                                continue;
+                            }
+                        };
+                        tracing::warn!("{}, tracker {} is not submitted", error, tracker.id);
+                        let _ = data.set_issue_error(&tracker.id, &error);
+                    }
+                }
+            }
+            tokio::time::sleep(ISSUE_RESOLVE_INTERVAL).await;
+        }
+    }
+
+    /// The explicitly chosen account, otherwise the account of the key's project
+    pub fn account_name(&self, key: &str, account: Option<&str>) -> String {
+        let project = key.split('-').next().unwrap_or_default();
+        account
+            .or_else(|| self.projects.get(project).map(String::as_str))
+            .unwrap_or(DEFAULT_ACCOUNT)
+            .to_string()
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Account, TrackerError> {
+        self.accounts
+            .get(name)
+            .ok_or(TrackerError::UnknownAccountError)
+    }
+
+    pub fn tracker_account(&self, tracker: &TrackerInformation) -> String {
+        self.account_name(&tracker.key, tracker.account.as_deref())
+    }
+
+    pub fn sink(
+        &self,
+        account: Option<&str>,
+        backend: Backend,
+    ) -> Result<&dyn WorklogSink, SinkError> {
+        let name = account.unwrap_or(DEFAULT_ACCOUNT);
+        self.accounts
+            .get(name)
+            .map(|account| account.sinks.get(backend))
+            .ok_or_else(|| SinkError::UnknownAccount(name.to_string()))
+    }
+
+    /// The sink a worklog is booked in
+    pub fn worklog_sink(&self, worklog: &Worklog) -> Result<&dyn WorklogSink, SinkError> {
+        self.sink(worklog.account.as_deref(), worklog.backend)
+    }
+
+    /// Fetches the booked worklogs of all accounts, reporting accounts which cannot be queried
+    pub async fn get_worklogs(&self, from: NaiveDate, to: NaiveDate) -> AccountWorklogs {
+        let results = join_all(self.accounts.iter().map(|(name, account)| async move {
+            (name, account.sinks.get_worklogs(from, to).await)
+        }))
+        .await;
+        let mut worklogs = AccountWorklogs::default();
+        for (name, result) in results {
+            match result {
+                Ok(booked) => {
+                    worklogs
+                        .worklogs
+                        .extend(booked.into_iter().map(|worklog| AccountWorklog {
+                            account: name.clone(),
+                            worklog,
+                        }))
+                }
+                Err(e) => worklogs.errors.push(AccountError {
+                    account: name.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        worklogs
+    }
+}
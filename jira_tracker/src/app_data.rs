@@ -1,94 +1,268 @@
 use core::option::Option;
 use core::result::Result;
 use core::result::Result::{Err, Ok};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::ops::{AddAssign, Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
 use std::time::{Duration, SystemTime};
 
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate};
 use indexmap::IndexMap;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use domain::TrackerInformation;
+use domain::{Segment, TempoAttributes, TrackerInformation};
 
 use crate::config::AppConfig;
+use crate::error::{ApiError, UpstreamFailure, UpstreamKind};
 use crate::files;
+use crate::http::HttpError;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum TrackerError {
     KeyFormatError,
     OccupiedError,
     NotFoundError,
+    AmbiguousKeyError,
     DurationAdjustmentError,
+    UnknownAccountError,
+    /// Jira rejected the configured credentials
+    UpstreamUnauthorizedError(UpstreamFailure),
+    /// The configured account is not allowed to access the issue
+    UpstreamForbiddenError(UpstreamFailure),
+    UpstreamRateLimitedError(UpstreamFailure),
+    /// Jira is not reachable or failed for transient reasons
+    UpstreamUnavailableError(UpstreamFailure),
+    /// Jira failed or answered with something unexpected
+    UpstreamError(UpstreamFailure),
 }
 
-impl IntoResponse for TrackerError {
-    fn into_response(self) -> Response {
-        let status_code = match self {
+impl TrackerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
             TrackerError::KeyFormatError => StatusCode::BAD_REQUEST,
             TrackerError::OccupiedError => StatusCode::CONFLICT,
             TrackerError::NotFoundError => StatusCode::NOT_FOUND,
+            TrackerError::AmbiguousKeyError => StatusCode::CONFLICT,
             TrackerError::DurationAdjustmentError => StatusCode::BAD_REQUEST,
+            TrackerError::UnknownAccountError => StatusCode::BAD_REQUEST,
+            _ => self.upstream_kind().unwrap().status(),
+        }
+    }
+
+    fn upstream_kind(&self) -> Option<UpstreamKind> {
+        match self {
+            TrackerError::UpstreamUnauthorizedError(_) => Some(UpstreamKind::Unauthorized),
+            TrackerError::UpstreamForbiddenError(_) => Some(UpstreamKind::Forbidden),
+            TrackerError::UpstreamRateLimitedError(_) => Some(UpstreamKind::RateLimited),
+            TrackerError::UpstreamUnavailableError(_) => Some(UpstreamKind::Unavailable),
+            TrackerError::UpstreamError(_) => Some(UpstreamKind::Failed),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            TrackerError::KeyFormatError => "key_format",
+            TrackerError::OccupiedError => "occupied",
+            TrackerError::NotFoundError => "not_found",
+            TrackerError::AmbiguousKeyError => "ambiguous_key",
+            TrackerError::DurationAdjustmentError => "duration_adjustment",
+            TrackerError::UnknownAccountError => "unknown_account",
+            _ => self.upstream_kind().unwrap().code(),
+        }
+    }
+}
+
+impl Display for TrackerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            TrackerError::KeyFormatError => "not a valid Jira issue key",
+            TrackerError::OccupiedError => "a tracker with the same key and description exists",
+            TrackerError::NotFoundError => "not found",
+            TrackerError::AmbiguousKeyError => "several trackers have this key, use the tracker id",
+            TrackerError::DurationAdjustmentError => {
+                "cannot subtract more than the tracked duration"
+            }
+            TrackerError::UnknownAccountError => "the account is not configured",
+            TrackerError::UpstreamUnauthorizedError(_) => {
+                "Jira rejected the configured credentials"
+            }
+            TrackerError::UpstreamForbiddenError(_) => {
+                "the configured account is not allowed to access the issue"
+            }
+            TrackerError::UpstreamRateLimitedError(_) => "the Jira rate limit has been exceeded",
+            TrackerError::UpstreamUnavailableError(_) => "Jira is not available",
+            TrackerError::UpstreamError(_) => "the request to Jira failed",
         };
-        status_code.into_response()
+        write!(f, "{}", message)
+    }
+}
+
+impl Error for TrackerError {}
+
+impl From<TrackerError> for ApiError {
+    fn from(value: TrackerError) -> Self {
+        let error = ApiError::new(value.status_code(), value.code(), value.to_string());
+        match value {
+            TrackerError::UnknownAccountError => error.field("account"),
+            TrackerError::UpstreamUnauthorizedError(upstream)
+            | TrackerError::UpstreamForbiddenError(upstream)
+            | TrackerError::UpstreamRateLimitedError(upstream)
+            | TrackerError::UpstreamUnavailableError(upstream)
+            | TrackerError::UpstreamError(upstream) => error.upstream(upstream),
+            _ => error,
+        }
+    }
+}
+
+impl IntoResponse for TrackerError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+impl From<HttpError> for TrackerError {
+    fn from(value: HttpError) -> Self {
+        let upstream = UpstreamFailure::from(&value);
+        if value.status() == Some(StatusCode::NOT_FOUND) {
+            return TrackerError::NotFoundError;
+        }
+        match UpstreamKind::of(&value) {
+            UpstreamKind::Unauthorized => TrackerError::UpstreamUnauthorizedError(upstream),
+            UpstreamKind::Forbidden => TrackerError::UpstreamForbiddenError(upstream),
+            UpstreamKind::RateLimited => TrackerError::UpstreamRateLimitedError(upstream),
+            UpstreamKind::Unavailable => TrackerError::UpstreamUnavailableError(upstream),
+            UpstreamKind::Failed => TrackerError::UpstreamError(upstream),
+        }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PausedTracker {
-    id: String,
+    #[serde(default)]
+    key: String,
+    #[serde(alias = "id", skip_serializing_if = "Option::is_none")]
+    issue_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    issue_error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     description: Option<String>,
-    duration: Duration,
+    #[serde(default = "Vec::new")]
+    segments: Vec<Segment>,
+    /// Accumulated duration of state files written before segments were recorded
+    #[serde(default, rename = "duration", skip_serializing)]
+    legacy_duration: Duration,
     #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
     positive_adjustments: Vec<Duration>,
     #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
     negative_adjustments: Vec<Duration>,
     start_time: DateTime<Local>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    submitted_seconds: BTreeMap<NaiveDate, u64>,
+    #[serde(default, skip_serializing_if = "TempoAttributes::is_empty")]
+    tempo: TempoAttributes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    account: Option<String>,
 }
 
 impl PausedTracker {
-    fn new<S: Into<String>>(id: S) -> Self {
+    fn new<K: Into<String>>(
+        key: K,
+        issue_id: Option<String>,
+        description: Option<String>,
+        account: Option<String>,
+    ) -> Self {
         Self {
-            id: id.into(),
-            description: None,
-            duration: Duration::default(),
+            key: key.into(),
+            issue_id,
+            issue_error: None,
+            description,
+            segments: Vec::new(),
+            legacy_duration: Duration::ZERO,
             positive_adjustments: Vec::new(),
             negative_adjustments: Vec::new(),
             start_time: Local::now(),
+            submitted_seconds: BTreeMap::new(),
+            tempo: TempoAttributes::default(),
+            account,
+        }
+    }
+}
+
+impl From<TrackerInformation> for PausedTracker {
+    /// Time which is not covered by the segments is restored as adjustment
+    fn from(tracker: TrackerInformation) -> Self {
+        let segments_sum: Duration = tracker.segments.iter().map(Segment::duration).sum();
+        let segments_sum = Duration::from_secs(segments_sum.as_secs());
+        Self {
+            key: tracker.key,
+            issue_id: tracker.issue_id,
+            issue_error: tracker.issue_error,
+            description: tracker.description,
+            segments: tracker.segments,
+            legacy_duration: Duration::ZERO,
+            positive_adjustments: Some(tracker.duration.saturating_sub(segments_sum))
+                .filter(|d| !d.is_zero())
+                .into_iter()
+                .collect(),
+            negative_adjustments: Some(segments_sum.saturating_sub(tracker.duration))
+                .filter(|d| !d.is_zero())
+                .into_iter()
+                .collect(),
+            start_time: tracker.start_time,
+            submitted_seconds: tracker.submitted_seconds,
+            tempo: tracker.tempo,
+            account: tracker.account,
         }
     }
 }
 
 impl AddAssign<&RunningTracker> for PausedTracker {
     fn add_assign(&mut self, rhs: &RunningTracker) {
-        self.duration += rhs.start_time.elapsed().unwrap_or_default();
+        self.segments.push(rhs.segment());
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct RunningTracker {
-    key: String,
+    #[serde(alias = "key")]
+    id: String,
     start_time: SystemTime,
 }
 
 impl RunningTracker {
-    fn new(key: &str) -> Self {
+    fn new(id: &str) -> Self {
         Self {
-            key: key.to_string(),
+            id: id.to_string(),
             start_time: SystemTime::now(),
         }
     }
+
+    fn segment(&self) -> Segment {
+        Segment {
+            start: self.start_time.into(),
+            end: Local::now(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct InnerAppData {
     running: Option<RunningTracker>,
+    /// Trackers by their generated tracker id
     trackers: IndexMap<String, PausedTracker>,
+    #[serde(default)]
+    next_id: u64,
+    /// Jira account ids by account name, so starting does not depend on Jira being reachable
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    account_ids: HashMap<String, String>,
 }
 
 impl InnerAppData {
@@ -96,154 +270,335 @@ impl InnerAppData {
         Self {
             running: None,
             trackers: IndexMap::new(),
+            next_id: 0,
+            account_ids: HashMap::new(),
         }
     }
 
-    fn elapsed(&self, key: &str) -> Option<Duration> {
-        self.trackers.get(key).map(|tracker| {
+    fn read<P: AsRef<Path>>(path: P) -> Result<Self, files::FileError> {
+        let data: Self = files::read_file(path)?;
+        Ok(data.migrate())
+    }
+
+    fn migrate(mut self) -> Self {
+        let data = &mut self;
+        // state files written before trackers had their own id are keyed by the jira key
+        let mut legacy_ids = Vec::new();
+        for (id, tracker) in data.trackers.iter_mut() {
+            if tracker.key.is_empty() {
+                tracker.key = id.clone();
+            }
+            if *id == tracker.key {
+                legacy_ids.push(id.clone());
+            }
+            if !tracker.legacy_duration.is_zero() {
+                let legacy_duration = std::mem::take(&mut tracker.legacy_duration);
+                tracker.positive_adjustments.push(legacy_duration);
+            }
+        }
+        // a key as id would take precedence over other trackers with the same key
+        let mut generated_ids = HashMap::new();
+        for legacy_id in legacy_ids {
+            let id = data.generate_id();
+            if let Some(running) = data.running.as_mut().filter(|r| r.id == legacy_id) {
+                running.id = id.clone();
+            }
+            generated_ids.insert(legacy_id, id);
+        }
+        data.trackers = std::mem::take(&mut data.trackers)
+            .into_iter()
+            .map(|(id, tracker)| (generated_ids.remove(&id).unwrap_or(id), tracker))
+            .collect();
+        self
+    }
+
+    fn generate_id(&mut self) -> String {
+        loop {
+            self.next_id += 1;
+            let id = self.next_id.to_string();
+            if !self.trackers.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+
+    /// Resolves either a tracker id or a jira key to the id of the matching tracker
+    fn resolve(&self, id_or_key: &str) -> Result<String, TrackerError> {
+        if self.trackers.contains_key(id_or_key) {
+            return Ok(id_or_key.to_string());
+        }
+        let mut matching = self
+            .trackers
+            .iter()
+            .filter(|(_, tracker)| tracker.key == id_or_key)
+            .map(|(id, _)| id);
+        match (matching.next(), matching.next()) {
+            (Some(id), None) => Ok(id.clone()),
+            (Some(_), Some(_)) => Err(TrackerError::AmbiguousKeyError),
+            (None, _) => Err(TrackerError::NotFoundError),
+        }
+    }
+
+    fn elapsed(&self, id: &str) -> Option<Duration> {
+        self.trackers.get(id).map(|tracker| {
             let running_duration = self
                 .running
                 .as_ref()
-                .filter(|r| r.key == key)
-                .map_or(Duration::ZERO, |r| {
-                    r.start_time.elapsed().unwrap_or_default()
-                });
+                .filter(|r| r.id == id)
+                .map_or(Duration::ZERO, |r| r.segment().duration());
+            let segments_sum: Duration = tracker.segments.iter().map(Segment::duration).sum();
             let positive_adjustments_sum: Duration = tracker.positive_adjustments.iter().sum();
             let negative_adjustments_sum: Duration = tracker.negative_adjustments.iter().sum();
-            let positive_duration_sum =
-                tracker.duration + running_duration + positive_adjustments_sum;
+            let positive_duration_sum = segments_sum + running_duration + positive_adjustments_sum;
             positive_duration_sum.saturating_sub(negative_adjustments_sum)
         })
     }
 
-    fn elapsed_seconds(&self, key: &str) -> Option<Duration> {
-        self.elapsed(key)
+    fn elapsed_seconds(&self, id: &str) -> Option<Duration> {
+        self.elapsed(id)
             .map(|elapsed| Duration::from_secs(elapsed.as_secs()))
     }
 
-    /// It is assumed that a tracker with the key exists
-    fn get_information(&self, key: &str) -> TrackerInformation {
-        let tracker = self.trackers.get(key).unwrap();
+    /// It is assumed that a tracker with the id exists
+    fn get_information(&self, id: &str) -> TrackerInformation {
+        let tracker = self.trackers.get(id).unwrap();
+        let running = self.running.as_ref().filter(|running| running.id == id);
+        let mut segments = tracker.segments.clone();
+        segments.extend(running.map(RunningTracker::segment));
         TrackerInformation {
-            key: key.to_owned(),
-            id: tracker.id.clone(),
+            id: id.to_owned(),
+            key: tracker.key.clone(),
+            issue_id: tracker.issue_id.clone(),
+            issue_error: tracker.issue_error.clone(),
             description: tracker.description.clone(),
-            duration: self.elapsed_seconds(key).unwrap(),
-            running: self
-                .running
-                .as_ref()
-                .filter(|running| running.key == key)
-                .is_some(),
+            duration: self.elapsed_seconds(id).unwrap(),
+            running: running.is_some(),
             start_time: tracker.start_time,
+            first_start: segments.first().map(|segment| segment.start),
+            last_start: segments.last().map(|segment| segment.start),
+            segments,
+            submitted_seconds: tracker.submitted_seconds.clone(),
+            tempo: tracker.tempo.clone(),
+            account: tracker.account.clone(),
         }
     }
 
     fn current(&self) -> Result<TrackerInformation, TrackerError> {
         self.running
             .as_ref()
-            .map(|running| self.get_information(&running.key))
+            .map(|running| self.get_information(&running.id))
             .ok_or(TrackerError::NotFoundError)
     }
 
-    fn get_tracker(&self, key: &str) -> Result<TrackerInformation, TrackerError> {
-        self.trackers
-            .get(key)
-            .map(|_| self.get_information(key))
-            .ok_or(TrackerError::NotFoundError)
+    fn get_tracker(&self, id: &str) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
+        Ok(self.get_information(&id))
     }
 
     fn list_trackers(&self) -> Vec<TrackerInformation> {
         self.trackers
             .keys()
-            .map(|key| self.get_information(key))
+            .map(|id| self.get_information(id))
             .collect()
     }
 
     fn set_description(
         &mut self,
-        key: &str,
+        id: &str,
         description: Option<String>,
     ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
         let description = description.filter(|d| !d.is_empty());
-        self.trackers
-            .get_mut(key)
-            .map(|tracker| tracker.description = description)
-            .ok_or(TrackerError::NotFoundError)?;
-        Ok(self.get_information(key))
+        self.trackers.get_mut(&id).unwrap().description = description;
+        Ok(self.get_information(&id))
+    }
+
+    fn set_tempo_attributes(
+        &mut self,
+        id: &str,
+        tempo: TempoAttributes,
+    ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
+        self.trackers.get_mut(&id).unwrap().tempo = tempo;
+        Ok(self.get_information(&id))
     }
 
     fn adjust_positive_duration(
         &mut self,
-        key: &str,
+        id: &str,
         duration: Duration,
     ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
         self.trackers
-            .get_mut(key)
-            .map(|tracker| tracker.positive_adjustments.push(duration))
-            .ok_or(TrackerError::NotFoundError)?;
-        Ok(self.get_information(key))
+            .get_mut(&id)
+            .unwrap()
+            .positive_adjustments
+            .push(duration);
+        Ok(self.get_information(&id))
     }
 
     fn adjust_negative_duration(
         &mut self,
-        key: &str,
+        id: &str,
         duration: Duration,
     ) -> Result<TrackerInformation, TrackerError> {
-        let elapsed = self.elapsed(key).ok_or(TrackerError::NotFoundError)?;
+        let id = self.resolve(id)?;
+        let elapsed = self.elapsed(&id).unwrap();
         if duration > elapsed {
             return Err(TrackerError::DurationAdjustmentError);
         }
 
         self.trackers
-            .get_mut(key)
+            .get_mut(&id)
             .unwrap()
             .negative_adjustments
             .push(duration);
-        Ok(self.get_information(key))
+        Ok(self.get_information(&id))
     }
 
-    fn start(&mut self, key: &str) -> Result<TrackerInformation, TrackerError> {
-        if !self.trackers.contains_key(key) {
-            return Err(TrackerError::NotFoundError);
+    /// Records the time tracked on the given days as submitted
+    fn mark_submitted(
+        &mut self,
+        id: &str,
+        days: Vec<(NaiveDate, Duration)>,
+    ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
+        let tracker = self.trackers.get_mut(&id).unwrap();
+        for (day, duration) in days {
+            *tracker.submitted_seconds.entry(day).or_default() += duration.as_secs();
         }
+        Ok(self.get_information(&id))
+    }
+
+    fn start(&mut self, id: &str) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
         self.pause();
-        self.running = Some(RunningTracker::new(key));
-        Ok(self.get_information(key))
+        self.running = Some(RunningTracker::new(&id));
+        Ok(self.get_information(&id))
     }
 
     fn pause(&mut self) {
         if let Some(running) = &self.running {
-            *self.trackers.get_mut(&running.key).unwrap() += running;
+            *self.trackers.get_mut(&running.id).unwrap() += running;
         }
         self.running = None;
     }
 
-    fn create_tracker(&mut self, key: &str, id: &str) -> Result<TrackerInformation, TrackerError> {
+    fn create_tracker(
+        &mut self,
+        key: &str,
+        issue_id: Option<String>,
+        description: Option<String>,
+        account: Option<String>,
+    ) -> Result<TrackerInformation, TrackerError> {
         if !Regex::new(r"\w+-\d+").unwrap().is_match(key) {
             return Err(TrackerError::KeyFormatError);
         }
-        if self.trackers.contains_key(key) {
+        let description = description.filter(|d| !d.is_empty());
+        if self
+            .trackers
+            .values()
+            .any(|tracker| tracker.key == key && tracker.description == description)
+        {
             return Err(TrackerError::OccupiedError);
         }
-        self.trackers
-            .insert(key.to_string(), PausedTracker::new(id));
-        Ok(self.get_information(key))
+        let id = self.generate_id();
+        self.trackers.insert(
+            id.clone(),
+            PausedTracker::new(key, issue_id, description, account),
+        );
+        Ok(self.get_information(&id))
     }
 
-    fn remove(&mut self, key: &str) -> Result<PausedTracker, TrackerError> {
-        if self.running.as_ref().filter(|t| t.key == key).is_some() {
+    fn set_issue_id(
+        &mut self,
+        id: &str,
+        issue_id: &str,
+    ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
+        self.trackers.get_mut(&id).unwrap().issue_id = Some(issue_id.to_string());
+        Ok(self.get_information(&id))
+    }
+
+    fn set_issue_error(
+        &mut self,
+        id: &str,
+        error: &str,
+    ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(id)?;
+        self.trackers.get_mut(&id).unwrap().issue_error = Some(error.to_string());
+        Ok(self.get_information(&id))
+    }
+
+    fn remove(&mut self, id: &str) -> Result<PausedTracker, TrackerError> {
+        let id = self.resolve(id)?;
+        if self.running.as_ref().filter(|t| t.id == id).is_some() {
             self.pause();
         }
-        self.trackers
-            .shift_remove(key)
-            .ok_or(TrackerError::NotFoundError)
+        Ok(self.trackers.shift_remove(&id).unwrap())
+    }
+
+    /// Replaces the submitted time of a tracker with the leftover time which was not submitted.
+    /// A running tracker keeps running and keeps the time since the submitted state was taken.
+    fn carry_over(
+        &mut self,
+        tracker: &TrackerInformation,
+        leftover: Duration,
+    ) -> Result<TrackerInformation, TrackerError> {
+        let id = self.resolve(&tracker.id)?;
+        let paused = self.trackers.get_mut(&id).unwrap();
+        paused.segments.clear();
+        paused.positive_adjustments = vec![leftover];
+        paused.negative_adjustments.clear();
+        paused.submitted_seconds.clear();
+        if let Some(running) = self.running.as_mut().filter(|running| running.id == id) {
+            if let Some(segment) = tracker.segments.last().filter(|_| tracker.running) {
+                running.start_time = segment.end.into();
+            }
+        }
+        Ok(self.get_information(&id))
+    }
+
+    /// Brings back a submitted tracker, or reopens the time of the given days if the tracker
+    /// still exists. Time which is not part of the tracker anymore, because the tracker only kept
+    /// its carried over leftover, is added back as adjustment.
+    fn restore(
+        &mut self,
+        tracker: TrackerInformation,
+        days: &[(NaiveDate, Duration)],
+    ) -> TrackerInformation {
+        if let Some(existing) = self
+            .trackers
+            .get_mut(&tracker.id)
+            .filter(|existing| existing.key == tracker.key)
+        {
+            let mut removed = Duration::ZERO;
+            for (day, duration) in days {
+                let seconds = existing.submitted_seconds.entry(*day).or_default();
+                let reopened = (*seconds).min(duration.as_secs());
+                *seconds -= reopened;
+                removed += duration.saturating_sub(Duration::from_secs(reopened));
+            }
+            existing.submitted_seconds.retain(|_, seconds| *seconds > 0);
+            if !removed.is_zero() {
+                existing.positive_adjustments.push(removed);
+            }
+            return self.get_information(&tracker.id);
+        }
+        let id = if self.trackers.contains_key(&tracker.id) {
+            self.generate_id()
+        } else {
+            tracker.id.clone()
+        };
+        self.trackers.insert(id.clone(), tracker.into());
+        self.get_information(&id)
     }
 
     fn remove_all(&mut self) -> Vec<PausedTracker> {
         self.pause();
         let map: Vec<String> = self.trackers.keys().map(|k| k.to_string()).collect();
         map.iter()
-            .map(|key| self.trackers.remove(key).unwrap())
+            .map(|id| self.trackers.remove(id).unwrap())
             .collect()
     }
 
@@ -288,8 +643,8 @@ impl AppData {
         self.reading(|a| a.current())
     }
 
-    pub fn get_tracker(&self, key: &str) -> Result<TrackerInformation, TrackerError> {
-        self.reading(|a| a.get_tracker(key))
+    pub fn get_tracker(&self, id: &str) -> Result<TrackerInformation, TrackerError> {
+        self.reading(|a| a.get_tracker(id))
     }
 
     pub fn list_trackers(&self) -> Vec<TrackerInformation> {
@@ -298,42 +653,97 @@ impl AppData {
 
     pub fn set_description(
         &self,
-        key: &str,
+        id: &str,
         description: Option<String>,
     ) -> Result<TrackerInformation, TrackerError> {
-        self.writing(|a| a.set_description(key, description))
+        self.writing(|a| a.set_description(id, description))
+    }
+
+    pub fn set_tempo_attributes(
+        &self,
+        id: &str,
+        tempo: TempoAttributes,
+    ) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.set_tempo_attributes(id, tempo))
     }
 
     pub fn adjust_positive_duration(
         &self,
-        key: &str,
+        id: &str,
         duration: Duration,
     ) -> Result<TrackerInformation, TrackerError> {
-        self.writing(|a| a.adjust_positive_duration(key, duration))
+        self.writing(|a| a.adjust_positive_duration(id, duration))
     }
 
     pub fn adjust_negative_duration(
         &self,
-        key: &str,
+        id: &str,
         duration: Duration,
     ) -> Result<TrackerInformation, TrackerError> {
-        self.writing(|a| a.adjust_negative_duration(key, duration))
+        self.writing(|a| a.adjust_negative_duration(id, duration))
+    }
+
+    pub fn mark_submitted(
+        &self,
+        id: &str,
+        days: Vec<(NaiveDate, Duration)>,
+    ) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.mark_submitted(id, days))
     }
 
-    pub fn start(&self, key: &str) -> Result<TrackerInformation, TrackerError> {
-        self.writing(|a| a.start(key))
+    pub fn start(&self, id: &str) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.start(id))
     }
 
     pub fn pause(&self) {
         self.writing(|a| a.pause())
     }
 
-    pub fn create_tracker(&self, key: &str, id: &str) -> Result<TrackerInformation, TrackerError> {
-        self.writing(|a| a.create_tracker(key, id))
+    pub fn create_tracker(
+        &self,
+        key: &str,
+        issue_id: Option<String>,
+        description: Option<String>,
+        account: Option<String>,
+    ) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.create_tracker(key, issue_id, description, account))
     }
 
-    pub fn remove(&self, key: &str) -> Result<PausedTracker, TrackerError> {
-        self.writing(|a| a.remove(key))
+    pub fn set_issue_id(
+        &self,
+        id: &str,
+        issue_id: &str,
+    ) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.set_issue_id(id, issue_id))
+    }
+
+    /// Records that the issue of the tracker cannot be resolved, which is not retried
+    pub fn set_issue_error(
+        &self,
+        id: &str,
+        error: &str,
+    ) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.set_issue_error(id, error))
+    }
+
+    pub fn remove(&self, id: &str) -> Result<PausedTracker, TrackerError> {
+        self.writing(|a| a.remove(id))
+    }
+
+    pub fn carry_over(
+        &self,
+        tracker: &TrackerInformation,
+        leftover: Duration,
+    ) -> Result<TrackerInformation, TrackerError> {
+        self.writing(|a| a.carry_over(tracker, leftover))
+    }
+
+    pub fn restore(
+        &self,
+        tracker: TrackerInformation,
+        days: &[(NaiveDate, Duration)],
+    ) -> TrackerInformation {
+        self.writing(|a| a.restore(tracker, days))
     }
 
     pub fn remove_all(&self) -> Vec<PausedTracker> {
@@ -344,19 +754,30 @@ impl AppData {
         self.reading(|a| a.sum())
     }
 
+    pub fn account_id(&self, account: &str) -> Option<String> {
+        self.reading(|a| a.account_ids.get(account).cloned())
+    }
+
+    pub fn set_account_id(&self, account: &str, account_id: &str) {
+        self.writing(|a| {
+            a.account_ids
+                .insert(account.to_string(), account_id.to_string())
+        });
+    }
+
     pub fn reload_state(&self) {
-        self.writing_without_flush(|a| *a = files::read_file(&self.path).unwrap())
+        self.writing_without_flush(|a| *a = InnerAppData::read(&self.path).unwrap())
     }
 }
 
 impl From<&AppConfig> for AppData {
     fn from(config: &AppConfig) -> Self {
         let path = &config.json_file;
-        let inner = files::read_file(path).unwrap_or_else(|e| {
+        let inner = InnerAppData::read(path).unwrap_or_else(|e| {
             if e.is_not_found() {
                 InnerAppData::new()
             } else {
-                Err(e).unwrap()
+                panic!("cannot read {}: {}", path.display(), e)
             }
         });
         AppData {
@@ -365,3 +786,162 @@ impl From<&AppConfig> for AppData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn migrates_trackers_keyed_by_jira_key() {
+        let state = json!({
+            "running": {
+                "key": "ABC-1",
+                "start_time": SystemTime::now(),
+            },
+            "trackers": {
+                "ABC-1": {
+                    "id": "10001",
+                    "duration": minutes(30),
+                    "start_time": Local::now(),
+                },
+                "ABC-2": {
+                    "id": "10002",
+                    "description": "review",
+                    "duration": minutes(60),
+                    "positive_adjustments": [minutes(5)],
+                    "start_time": Local::now(),
+                },
+            },
+        });
+        let data = serde_json::from_value::<InnerAppData>(state)
+            .unwrap()
+            .migrate();
+
+        assert_eq!(data.trackers.keys().collect::<Vec<_>>(), vec!["1", "2"]);
+        assert_eq!(data.running.as_ref().unwrap().id, "1");
+
+        let running = data.get_information("1");
+        assert_eq!(running.key, "ABC-1");
+        assert_eq!(running.issue_id.as_deref(), Some("10001"));
+        assert!(running.running);
+        assert!(running.duration >= minutes(30) && running.duration < minutes(31));
+
+        let paused = data.get_information("2");
+        assert_eq!(paused.key, "ABC-2");
+        assert_eq!(paused.issue_id.as_deref(), Some("10002"));
+        assert_eq!(paused.description.as_deref(), Some("review"));
+        assert_eq!(paused.duration, minutes(65));
+        assert_eq!(data.resolve("ABC-2").unwrap(), "2");
+    }
+
+    #[test]
+    fn submitted_seconds_accumulate_per_day() {
+        let mut data = InnerAppData::new();
+        let id = data.create_tracker("ABC-1", None, None, None).unwrap().id;
+        let day = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let next_day = day.succ_opt().unwrap();
+        data.mark_submitted(&id, vec![(day, minutes(30))]).unwrap();
+        let tracker = data
+            .mark_submitted(&id, vec![(day, minutes(15)), (next_day, minutes(10))])
+            .unwrap();
+        assert_eq!(
+            tracker.submitted_seconds,
+            BTreeMap::from([(day, 45 * 60), (next_day, 10 * 60)])
+        );
+    }
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 4).unwrap()
+    }
+
+    #[test]
+    fn restore_brings_back_a_removed_tracker() {
+        let mut data = InnerAppData::new();
+        let id = data.create_tracker("ABC-1", None, None, None).unwrap().id;
+        let submitted = data.adjust_positive_duration(&id, minutes(60)).unwrap();
+        data.remove(&id).unwrap();
+
+        let restored = data.restore(submitted, &[(day(), minutes(60))]);
+        assert_eq!(restored.id, id);
+        assert_eq!(restored.key, "ABC-1");
+        assert_eq!(restored.duration, minutes(60));
+    }
+
+    #[test]
+    fn restore_does_not_replace_a_tracker_which_took_over_the_id() {
+        let mut data = InnerAppData::new();
+        let submitted = data.create_tracker("ABC-1", None, None, None).unwrap();
+        data.remove(&submitted.id).unwrap();
+        data.next_id = 0;
+        let other = data.create_tracker("ABC-2", None, None, None).unwrap();
+        assert_eq!(other.id, submitted.id);
+
+        let restored = data.restore(submitted, &[]);
+        assert_ne!(restored.id, other.id);
+        assert_eq!(data.get_information(&other.id).key, "ABC-2");
+        assert_eq!(data.get_information(&restored.id).key, "ABC-1");
+    }
+
+    #[test]
+    fn restore_reopens_the_submitted_days_of_an_existing_tracker() {
+        let mut data = InnerAppData::new();
+        let id = data.create_tracker("ABC-1", None, None, None).unwrap().id;
+        let next_day = day().succ_opt().unwrap();
+        let submitted = data
+            .mark_submitted(&id, vec![(day(), minutes(60)), (next_day, minutes(30))])
+            .unwrap();
+
+        let restored = data.restore(submitted, &[(day(), minutes(45)), (next_day, minutes(30))]);
+        assert_eq!(
+            restored.submitted_seconds,
+            BTreeMap::from([(day(), 15 * 60)])
+        );
+    }
+
+    #[test]
+    fn restore_adds_back_time_which_was_replaced_by_the_carried_over_leftover() {
+        let mut data = InnerAppData::new();
+        let id = data.create_tracker("ABC-1", None, None, None).unwrap().id;
+        let leftover = Duration::from_secs(30);
+        let submitted = data
+            .adjust_positive_duration(&id, minutes(70) + leftover)
+            .unwrap();
+        let carried_over = data.carry_over(&submitted, leftover).unwrap();
+        assert_eq!(carried_over.duration, leftover);
+
+        let restored = data.restore(submitted, &[(day(), minutes(70))]);
+        assert_eq!(restored.id, id);
+        assert_eq!(restored.duration, minutes(70) + leftover);
+        assert!(restored.submitted_seconds.is_empty());
+    }
+
+    #[test]
+    fn upstream_failures_are_classified_like_sink_errors() {
+        let failure = |status: StatusCode| HttpError::Status {
+            status,
+            message: String::new(),
+            retry_after: None,
+        };
+        for (status, code) in [
+            (StatusCode::UNAUTHORIZED, "upstream_unauthorized"),
+            (StatusCode::FORBIDDEN, "upstream_forbidden"),
+            (StatusCode::TOO_MANY_REQUESTS, "upstream_rate_limited"),
+            (StatusCode::SERVICE_UNAVAILABLE, "upstream_unavailable"),
+            (StatusCode::BAD_REQUEST, "upstream_error"),
+        ] {
+            let error = TrackerError::from(failure(status));
+            let kind = UpstreamKind::of(&failure(status));
+            assert_eq!(error.code(), code);
+            assert_eq!(kind.code(), code);
+            assert_eq!(error.status_code(), kind.status());
+        }
+        let not_found = TrackerError::from(failure(StatusCode::NOT_FOUND));
+        assert!(matches!(not_found, TrackerError::NotFoundError));
+    }
+}
@@ -1,11 +1,13 @@
-use std::error::Error;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use axum::http::StatusCode;
-use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use domain::{TempoAttributes, TrackerInformation};
 use figment::providers::Env;
 use figment::Figment;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
 use tower_http::trace::TraceLayer;
 use tracing::Level;
@@ -13,7 +15,17 @@ use tracing_subscriber::filter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+use crate::schedule::Schedule;
+
 const DEFAULT_PORT: fn() -> u16 = || 8080;
+const DEFAULT_GRANULARITY: fn() -> Duration = || Duration::from_secs(60);
+const DEFAULT_MINIMUM: fn() -> Duration = || Duration::from_secs(60);
+const DEFAULT_ACCOUNT_ATTRIBUTE: fn() -> String = || "_Account_".to_string();
+const DEFAULT_JIRA_URL: fn() -> String = || "https://anevis.atlassian.net".to_string();
+const DEFAULT_TEMPO_URL: fn() -> String = || "https://api.tempo.io".to_string();
+const DEFAULT_CONNECT_TIMEOUT: fn() -> Duration = || Duration::from_secs(10);
+const DEFAULT_REQUEST_TIMEOUT: fn() -> Duration = || Duration::from_secs(30);
+const DEFAULT_MAX_RETRIES: fn() -> u32 = || 3;
 
 fn deserialize_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
 where
@@ -23,40 +35,252 @@ where
     Ok(PathBuf::from(shellexpand::full(&string).unwrap().as_ref()))
 }
 
+fn deserialize_timezone<'de, D>(deserializer: D) -> Result<Timezone, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    let timezone = name.parse().map_err(serde::de::Error::custom)?;
+    Ok(Timezone::Named(timezone))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
-    pub jira_email: String,
-    pub jira_api_token: String,
-    pub tempo_api_token: String,
+    /// The account of trackers which are not associated with one of the named accounts
+    #[serde(flatten)]
+    pub default_account: AccountConfig,
+    /// Further accounts by their name
+    #[serde(default)]
+    pub accounts: HashMap<String, AccountConfig>,
     #[serde(default = "DEFAULT_PORT")]
     pub tracker_port: u16,
     #[serde(deserialize_with = "deserialize_path")]
     pub json_file: PathBuf,
+    #[serde(default)]
+    pub worklog_start: WorklogStart,
+    /// The time zone in which tracked time is split into days, e.g. `Europe/Berlin`
+    #[serde(default, deserialize_with = "deserialize_timezone")]
+    pub timezone: Timezone,
+    #[serde(flatten)]
+    pub submit_policy: SubmitPolicy,
+    #[serde(flatten)]
+    pub http: HttpConfig,
+    /// Key of the work attribute the Tempo account is sent as
+    #[serde(default = "DEFAULT_ACCOUNT_ATTRIBUTE")]
+    pub tempo_account_attribute: String,
+    /// Tempo attributes of trackers by jira project key
+    #[serde(default)]
+    pub tempo_defaults: HashMap<String, TempoAttributes>,
+    #[serde(default)]
+    pub worklog_backend: Backend,
+    /// Worklog backends by jira project key, overriding the default backend
+    #[serde(default)]
+    pub worklog_backends: HashMap<String, Backend>,
+    /// When to pause and submit all trackers automatically
+    pub submit_schedule: Option<Schedule>,
 }
 
-impl AppConfig {
-    pub fn new() -> Self {
-        let figment = Figment::from(Env::raw());
-        figment.extract().unwrap()
+/// Credentials and sites of a Jira and Tempo account
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountConfig {
+    /// Only needed for Jira Cloud
+    pub jira_email: Option<String>,
+    /// API token for Jira Cloud, personal access token for Data Center
+    pub jira_api_token: String,
+    #[serde(default)]
+    pub jira_deployment: JiraDeployment,
+    /// Only needed if worklogs are booked in Tempo
+    pub tempo_api_token: Option<String>,
+    /// Base URL of the Jira site, e.g. `https://example.atlassian.net`
+    #[serde(default = "DEFAULT_JIRA_URL")]
+    pub jira_url: String,
+    #[serde(default = "DEFAULT_TEMPO_URL")]
+    pub tempo_url: String,
+    /// Jira project keys whose trackers are booked with the account
+    #[serde(default)]
+    pub projects: Vec<String>,
+}
+
+/// Jira Cloud authenticates with email and API token, Data Center and Server with a personal
+/// access token
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JiraDeployment {
+    #[default]
+    Cloud,
+    DataCenter,
+}
+
+/// Timeouts and retries of the requests to Jira and Tempo
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    #[serde(
+        default = "DEFAULT_CONNECT_TIMEOUT",
+        rename = "http_connect_timeout",
+        with = "humantime_serde"
+    )]
+    pub connect_timeout: Duration,
+    /// Time until a whole request including the response body has to be completed
+    #[serde(
+        default = "DEFAULT_REQUEST_TIMEOUT",
+        rename = "http_request_timeout",
+        with = "humantime_serde"
+    )]
+    pub request_timeout: Duration,
+    /// How often a failed request is repeated before giving up
+    #[serde(default = "DEFAULT_MAX_RETRIES", rename = "http_max_retries")]
+    pub max_retries: u32,
+}
+
+/// How tracked durations are turned into submitted worklog durations
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubmitPolicy {
+    #[serde(default, rename = "submit_rounding")]
+    pub rounding: Rounding,
+    #[serde(
+        default = "DEFAULT_GRANULARITY",
+        rename = "submit_granularity",
+        with = "humantime_serde"
+    )]
+    pub granularity: Duration,
+    /// Worklogs shorter than this after rounding are not submitted
+    #[serde(
+        default = "DEFAULT_MINIMUM",
+        rename = "submit_minimum",
+        with = "humantime_serde"
+    )]
+    pub minimum: Duration,
+    /// Keep time which is not submitted in the tracker instead of discarding it
+    #[serde(default, rename = "submit_carry_over")]
+    pub carry_over: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    #[default]
+    None,
+    Up,
+    Down,
+    Nearest,
+}
+
+impl SubmitPolicy {
+    pub fn round(&self, duration: Duration) -> Duration {
+        let seconds = duration.as_secs();
+        let granularity = self.granularity.as_secs().max(1);
+        let rounded = match self.rounding {
+            Rounding::None => seconds,
+            Rounding::Up => seconds.div_ceil(granularity) * granularity,
+            Rounding::Down => seconds / granularity * granularity,
+            Rounding::Nearest => (seconds + granularity / 2) / granularity * granularity,
+        };
+        Duration::from_secs(rounded)
     }
 }
 
-pub struct LogError(Box<dyn Error>);
+/// A time zone of the tz database, the time zone of the system by default
+#[derive(Debug, Default, Clone, Copy)]
+pub enum Timezone {
+    #[default]
+    System,
+    Named(Tz),
+}
 
-impl<E> From<E> for LogError
-where
-    E: Error + 'static,
-{
-    fn from(value: E) -> Self {
-        LogError(Box::new(value))
+impl Timezone {
+    /// The same point in time with the offset of this time zone
+    pub fn convert(&self, time: DateTime<Local>) -> DateTime<FixedOffset> {
+        match self {
+            Timezone::System => time.fixed_offset(),
+            Timezone::Named(timezone) => time.with_timezone(timezone).fixed_offset(),
+        }
+    }
+
+    /// The current day in this time zone
+    pub fn today(&self) -> NaiveDate {
+        self.convert(Local::now()).date_naive()
+    }
+
+    /// The earliest point in time with the given wall clock time, if it exists in this time zone
+    pub fn at(&self, time: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Timezone::System => time
+                .and_local_timezone(Local)
+                .earliest()
+                .map(|time| time.fixed_offset()),
+            Timezone::Named(timezone) => time
+                .and_local_timezone(*timezone)
+                .earliest()
+                .map(|time| time.fixed_offset()),
+        }
+    }
+}
+
+/// The service worklogs are booked in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    #[default]
+    Tempo,
+    Jira,
+}
+
+/// Which point in time of a tracker is used as the start of its worklog
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorklogStart {
+    #[default]
+    FirstStart,
+    LastStart,
+    Creation,
+}
+
+impl WorklogStart {
+    /// Trackers which have never been running fall back to their creation time
+    pub fn start_time(&self, tracker: &TrackerInformation) -> DateTime<Local> {
+        match self {
+            WorklogStart::FirstStart => tracker.first_start,
+            WorklogStart::LastStart => tracker.last_start,
+            WorklogStart::Creation => None,
+        }
+        .unwrap_or(tracker.start_time)
     }
 }
 
-impl IntoResponse for LogError {
-    fn into_response(self) -> Response {
-        let LogError(error) = self;
-        eprintln!("Internal Server Error: {}", error);
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+impl AppConfig {
+    pub fn new() -> Result<Self, Box<figment::Error>> {
+        let figment = Figment::from(Env::raw());
+        let config: Self = figment.extract()?;
+        config.validate().map_err(figment::Error::from)?;
+        Ok(config)
+    }
+
+    /// Checks the credentials which are only required by some deployments and backends
+    fn validate(&self) -> Result<(), String> {
+        let uses_tempo = self.worklog_backend == Backend::Tempo
+            || self.worklog_backends.values().any(|b| *b == Backend::Tempo);
+        let accounts = Some((None, &self.default_account))
+            .into_iter()
+            .chain(self.accounts.iter().map(|(name, a)| (Some(name), a)));
+        for (name, account) in accounts {
+            let setting = |variable: &str| match name {
+                Some(name) => format!("{} of account {}", variable.to_lowercase(), name),
+                None => variable.to_string(),
+            };
+            if account.jira_deployment == JiraDeployment::Cloud && account.jira_email.is_none() {
+                return Err(format!(
+                    "{} is required for Jira Cloud",
+                    setting("JIRA_EMAIL")
+                ));
+            }
+            if uses_tempo && account.tempo_api_token.is_none() {
+                return Err(format!(
+                    "{} is required unless worklogs are booked in Jira",
+                    setting("TEMPO_API_TOKEN")
+                ));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -75,3 +299,49 @@ pub fn setup_logging() -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>> {
 
     TraceLayer::new_for_http()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(rounding: Rounding, granularity: u64) -> SubmitPolicy {
+        SubmitPolicy {
+            rounding,
+            granularity: Duration::from_secs(granularity),
+            minimum: Duration::ZERO,
+            carry_over: false,
+        }
+    }
+
+    #[test]
+    fn rounds_to_granularity() {
+        let duration = Duration::from_secs(22 * 60);
+        let minutes = |rounding| policy(rounding, 15 * 60).round(duration).as_secs() / 60;
+        assert_eq!(minutes(Rounding::None), 22);
+        assert_eq!(minutes(Rounding::Up), 30);
+        assert_eq!(minutes(Rounding::Down), 15);
+        assert_eq!(minutes(Rounding::Nearest), 15);
+        assert_eq!(
+            policy(Rounding::Nearest, 15 * 60)
+                .round(Duration::from_secs(23 * 60))
+                .as_secs(),
+            30 * 60
+        );
+    }
+
+    #[test]
+    fn keeps_exact_multiples() {
+        let duration = Duration::from_secs(30 * 60);
+        for rounding in [Rounding::Up, Rounding::Down, Rounding::Nearest] {
+            assert_eq!(policy(rounding, 15 * 60).round(duration), duration);
+        }
+    }
+
+    #[test]
+    fn granularity_zero_rounds_to_seconds() {
+        let duration = Duration::from_millis(61_500);
+        for rounding in [Rounding::Up, Rounding::Down, Rounding::Nearest] {
+            assert_eq!(policy(rounding, 0).round(duration), Duration::from_secs(61));
+        }
+    }
+}
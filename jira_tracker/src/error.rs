@@ -0,0 +1,189 @@
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::http::header::RETRY_AFTER;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+use crate::http::HttpError;
+
+/// What Jira or Tempo responded with
+#[derive(Debug, Serialize)]
+pub struct UpstreamFailure {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// The error messages or body of the response, the failure itself if there was no response
+    pub message: String,
+    /// Seconds to wait before trying again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+impl From<&HttpError> for UpstreamFailure {
+    fn from(value: &HttpError) -> Self {
+        Self {
+            status: value.status().map(|status| status.as_u16()),
+            message: value
+                .response_message()
+                .map_or_else(|| value.to_string(), str::to_string),
+            retry_after: value.retry_after().map(|retry_after| retry_after.as_secs()),
+        }
+    }
+}
+
+/// How a request to Jira or Tempo failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamKind {
+    Unauthorized,
+    Forbidden,
+    RateLimited,
+    /// The request might succeed later
+    Unavailable,
+    Failed,
+}
+
+impl UpstreamKind {
+    pub fn of(error: &HttpError) -> Self {
+        match error.status() {
+            Some(StatusCode::UNAUTHORIZED) => UpstreamKind::Unauthorized,
+            Some(StatusCode::FORBIDDEN) => UpstreamKind::Forbidden,
+            Some(StatusCode::TOO_MANY_REQUESTS) => UpstreamKind::RateLimited,
+            _ if error.is_transient() => UpstreamKind::Unavailable,
+            _ => UpstreamKind::Failed,
+        }
+    }
+
+    /// The status failed requests are answered with, failures of the configured credentials are
+    /// gateway errors rather than failures of the client
+    pub fn status(self) -> StatusCode {
+        match self {
+            UpstreamKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            UpstreamKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// Machine-readable code of the failure
+    pub fn code(self) -> &'static str {
+        match self {
+            UpstreamKind::Unauthorized => "upstream_unauthorized",
+            UpstreamKind::Forbidden => "upstream_forbidden",
+            UpstreamKind::RateLimited => "upstream_rate_limited",
+            UpstreamKind::Unavailable => "upstream_unavailable",
+            UpstreamKind::Failed => "upstream_error",
+        }
+    }
+}
+
+/// The JSON body all failed requests are answered with
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    /// Machine-readable kind of the error, e.g. `not_found`
+    code: &'static str,
+    message: String,
+    /// The tracker id, issue key or entry id the error refers to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    /// The request field the error refers to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<UpstreamFailure>,
+    /// Further values specific to the error
+    #[serde(flatten)]
+    details: serde_json::Map<String, serde_json::Value>,
+}
+
+/// An error response of the HTTP API
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: Box<ErrorBody>,
+}
+
+impl ApiError {
+    pub fn new<M: Into<String>>(status: StatusCode, code: &'static str, message: M) -> Self {
+        let body = ErrorBody {
+            code,
+            message: message.into(),
+            key: None,
+            field: None,
+            upstream: None,
+            details: serde_json::Map::new(),
+        };
+        Self {
+            status,
+            body: Box::new(body),
+        }
+    }
+
+    pub fn key<K: Into<String>>(mut self, key: K) -> Self {
+        self.body.key = Some(key.into());
+        self
+    }
+
+    pub fn field<F: Into<String>>(mut self, field: F) -> Self {
+        self.body.field = Some(field.into());
+        self
+    }
+
+    pub fn upstream(mut self, upstream: UpstreamFailure) -> Self {
+        self.body.upstream = Some(upstream);
+        self
+    }
+
+    pub fn detail<V: Serialize>(mut self, name: &str, value: V) -> Self {
+        self.body
+            .details
+            .insert(name.to_string(), serde_json::to_value(value).unwrap());
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let ErrorBody {
+            code, message, key, ..
+        } = self.body.as_ref();
+        if self.status.is_server_error() {
+            tracing::error!(code, ?key, "{}", message);
+        } else {
+            tracing::debug!(code, ?key, "{}", message);
+        }
+        let retry_after = self
+            .body
+            .upstream
+            .as_ref()
+            .and_then(|upstream| upstream.retry_after);
+        let mut response = (self.status, Json(self.body)).into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.into());
+        }
+        response
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(value: JsonRejection) -> Self {
+        ApiError::new(value.status(), "invalid_body", value.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(value: QueryRejection) -> Self {
+        ApiError::new(value.status(), "invalid_query", value.body_text())
+    }
+}
+
+pub trait ErrorKey<T> {
+    /// Attaches the tracker id, issue key or entry id the failed operation refers to
+    fn for_key(self, key: &str) -> Result<T, ApiError>;
+}
+
+impl<T, E: Into<ApiError>> ErrorKey<T> for Result<T, E> {
+    fn for_key(self, key: &str) -> Result<T, ApiError> {
+        self.map_err(|e| e.into().key(key))
+    }
+}
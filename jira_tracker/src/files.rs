@@ -1,6 +1,9 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::{BufReader, BufWriter, ErrorKind, Write};
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 use std::{fs, io};
 
 use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
@@ -24,6 +27,17 @@ impl FileError {
     }
 }
 
+impl Display for FileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError::IO(e) => write!(f, "{}", e),
+            FileError::Serde(e) => write!(f, "invalid json: {}", e),
+        }
+    }
+}
+
+impl Error for FileError {}
+
 pub fn read_file<P, D>(path: P) -> Result<D, FileError>
 where
     P: AsRef<Path>,
@@ -50,6 +64,46 @@ where
     Ok(())
 }
 
+/// State which is kept in memory and written to its file after every change
+#[derive(Debug)]
+pub struct StateFile<T> {
+    state: RwLock<T>,
+    path: PathBuf,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> StateFile<T> {
+    /// Starts with the default state if the file does not exist yet
+    pub fn load(path: PathBuf) -> Self {
+        let state = read_file(&path).unwrap_or_else(|e| {
+            if e.is_not_found() {
+                T::default()
+            } else {
+                panic!("cannot read {}: {}", path.display(), e)
+            }
+        });
+        Self {
+            state: RwLock::new(state),
+            path,
+        }
+    }
+
+    pub fn reading<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.state.read().unwrap())
+    }
+
+    pub fn writing<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let result = f(&mut self.state.write().unwrap());
+        self.reading(|state| write_file(&self.path, state).unwrap());
+        result
+    }
+}
+
 #[must_use]
 pub fn watch_file<P, F>(path: P, handler: F) -> RecommendedWatcher
 where
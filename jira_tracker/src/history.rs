@@ -0,0 +1,129 @@
+use chrono::{DateTime, Local, NaiveDate};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use domain::TrackerInformation;
+
+use crate::app_data::TrackerError;
+use crate::config::AppConfig;
+use crate::files::StateFile;
+use crate::worklog_sink::SubmittedWorklog;
+
+/// A submitted tracker together with the worklogs created for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub submitted_at: DateTime<Local>,
+    pub tracker: TrackerInformation,
+    pub worklogs: Vec<SubmittedWorklog>,
+}
+
+impl HistoryEntry {
+    /// Restricts the worklogs to the ones starting within the given days, if any are left
+    fn within(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Option<HistoryEntry> {
+        let worklogs: Vec<_> = self
+            .worklogs
+            .iter()
+            .filter(|submitted| {
+                let day = submitted.worklog.start.date_naive();
+                from.is_none_or(|from| from <= day) && to.is_none_or(|to| day <= to)
+            })
+            .cloned()
+            .collect();
+        if worklogs.is_empty() && (from.is_some() || to.is_some()) {
+            return None;
+        }
+        Some(HistoryEntry {
+            worklogs,
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InnerHistory {
+    entries: IndexMap<String, HistoryEntry>,
+    next_id: u64,
+}
+
+impl InnerHistory {
+    fn create(&mut self, tracker: TrackerInformation, worklogs: Vec<SubmittedWorklog>) -> String {
+        self.next_id += 1;
+        let id = self.next_id.to_string();
+        let entry = HistoryEntry {
+            id: id.clone(),
+            submitted_at: Local::now(),
+            tracker,
+            worklogs,
+        };
+        self.entries.insert(id.clone(), entry);
+        id
+    }
+
+    fn add_worklog(&mut self, id: &str, worklog: SubmittedWorklog) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.worklogs.push(worklog);
+        }
+    }
+
+    fn remove_worklogs(&mut self, id: &str, worklog_ids: &[u64]) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry
+                .worklogs
+                .retain(|worklog| !worklog_ids.contains(&worklog.worklog_id));
+        }
+    }
+
+    fn get(&self, id: &str) -> Result<HistoryEntry, TrackerError> {
+        self.entries
+            .get(id)
+            .cloned()
+            .ok_or(TrackerError::NotFoundError)
+    }
+
+    fn list(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Vec<HistoryEntry> {
+        self.entries
+            .values()
+            .filter_map(|entry| entry.within(from, to))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct History {
+    state: StateFile<InnerHistory>,
+}
+
+impl History {
+    pub fn create(&self, tracker: TrackerInformation, worklogs: Vec<SubmittedWorklog>) -> String {
+        self.state.writing(|a| a.create(tracker, worklogs))
+    }
+
+    pub fn add_worklog(&self, id: &str, worklog: SubmittedWorklog) {
+        self.state.writing(|a| a.add_worklog(id, worklog))
+    }
+
+    pub fn remove_worklogs(&self, id: &str, worklog_ids: &[u64]) {
+        self.state.writing(|a| a.remove_worklogs(id, worklog_ids))
+    }
+
+    pub fn remove(&self, id: &str) -> Option<HistoryEntry> {
+        self.state.writing(|a| a.entries.shift_remove(id))
+    }
+
+    pub fn get(&self, id: &str) -> Result<HistoryEntry, TrackerError> {
+        self.state.reading(|a| a.get(id))
+    }
+
+    pub fn list(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Vec<HistoryEntry> {
+        self.state.reading(|a| a.list(from, to))
+    }
+}
+
+impl From<&AppConfig> for History {
+    fn from(config: &AppConfig) -> Self {
+        History {
+            state: StateFile::load(config.json_file.with_extension("history.json")),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{IntoUrl, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::config::HttpConfig;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Rate limits asking to wait longer fail the request right away, leaving the retry to the caller
+const MAXIMUM_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+fn backoff(attempts: u32) -> Duration {
+    INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// The request did not complete within the configured timeout
+    Timeout,
+    /// No connection could be established, so the request was not processed
+    Connect(reqwest::Error),
+    Request(reqwest::Error),
+    /// The service answered with an unsuccessful status
+    Status {
+        status: StatusCode,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<HttpError>,
+    },
+    /// A request which is not idempotent failed after it might have been processed
+    Unconfirmed(Box<HttpError>),
+}
+
+impl HttpError {
+    /// Whether trying again later might succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            HttpError::Timeout | HttpError::Connect(_) => true,
+            HttpError::Request(e) => !(e.is_decode() || e.is_builder() || e.is_redirect()),
+            HttpError::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            HttpError::RetriesExhausted { last, .. } | HttpError::Unconfirmed(last) => {
+                last.is_transient()
+            }
+        }
+    }
+
+    /// Whether the request might have been processed despite failing, so sending it again may
+    /// process it twice
+    pub fn is_unconfirmed(&self) -> bool {
+        matches!(self, HttpError::Unconfirmed(_))
+    }
+
+    /// The status of the last response, if there was one
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            HttpError::Status { status, .. } => Some(*status),
+            HttpError::RetriesExhausted { last, .. } | HttpError::Unconfirmed(last) => {
+                last.status()
+            }
+            _ => None,
+        }
+    }
+
+    /// The messages or body of the last unsuccessful response
+    pub fn response_message(&self) -> Option<&str> {
+        match self {
+            HttpError::Status { message, .. } => Some(message),
+            HttpError::RetriesExhausted { last, .. } | HttpError::Unconfirmed(last) => {
+                last.response_message()
+            }
+            _ => None,
+        }
+    }
+
+    /// How long the service asked to wait because of its rate limit
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            HttpError::Status { retry_after, .. } => *retry_after,
+            HttpError::RetriesExhausted { last, .. } | HttpError::Unconfirmed(last) => {
+                last.retry_after()
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the request may be sent again without risking to process it twice
+    fn was_rejected(&self) -> bool {
+        match self {
+            HttpError::Connect(_) => true,
+            HttpError::Status { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS,
+            _ => false,
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::Timeout => write!(f, "request timed out"),
+            HttpError::Connect(e) => write!(f, "could not connect: {}", e),
+            HttpError::Request(e) => write!(f, "{}", e),
+            HttpError::Status {
+                status, message, ..
+            } => write!(f, "{}: {}", status, message),
+            HttpError::RetriesExhausted { attempts, last } => {
+                write!(f, "giving up after {} attempts: {}", attempts, last)
+            }
+            HttpError::Unconfirmed(e) => write!(f, "{} (the request might have been processed)", e),
+        }
+    }
+}
+
+impl Error for HttpError {}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(value: reqwest::Error) -> Self {
+        if value.is_timeout() {
+            HttpError::Timeout
+        } else if value.is_connect() {
+            HttpError::Connect(value)
+        } else {
+            HttpError::Request(value)
+        }
+    }
+}
+
+/// Error bodies of Tempo and Jira
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    Tempo {
+        errors: Vec<ErrorMessage>,
+    },
+    Jira {
+        #[serde(default, rename = "errorMessages")]
+        error_messages: Vec<String>,
+        #[serde(default)]
+        errors: HashMap<String, String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorMessage {
+    message: String,
+}
+
+impl ErrorBody {
+    fn messages(self) -> Vec<String> {
+        match self {
+            ErrorBody::Tempo { errors } => errors.into_iter().map(|error| error.message).collect(),
+            ErrorBody::Jira {
+                error_messages,
+                errors,
+            } => error_messages
+                .into_iter()
+                .chain(
+                    errors
+                        .into_iter()
+                        .map(|(field, message)| format!("{}: {}", field, message)),
+                )
+                .collect(),
+        }
+    }
+}
+
+/// The delay of a `Retry-After` header, which is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Turns unsuccessful responses into an error containing the messages reported by the service
+async fn check_response(response: Response) -> Result<Response, HttpError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let text = response.text().await?;
+    let message = match serde_json::from_str::<ErrorBody>(&text) {
+        Ok(body) => body.messages().join(", "),
+        Err(_) => text,
+    };
+    Err(HttpError::Status {
+        status,
+        message,
+        retry_after,
+    })
+}
+
+/// Reads the JSON body of a successful response to a request which is not idempotent.
+///
+/// The request has been processed, so failing to read the body is unconfirmed: sending the
+/// request again may process it twice.
+pub async fn processed_json<T: DeserializeOwned>(response: Response) -> Result<T, HttpError> {
+    response
+        .json()
+        .await
+        .map_err(|e| HttpError::Unconfirmed(Box::new(e.into())))
+}
+
+/// A client with timeouts which retries requests failing for transient reasons
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    max_retries: u32,
+}
+
+impl HttpClient {
+    pub fn new(headers: HeaderMap, config: &HttpConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout)
+            .build()
+            .unwrap();
+        Self {
+            client,
+            max_retries: config.max_retries,
+        }
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn delete<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    /// Sends the request and fails for unsuccessful responses.
+    ///
+    /// Idempotent requests are retried with backoff on all transient failures. Other requests are
+    /// only retried if they were rejected without being processed, i.e. if no connection could be
+    /// established or a rate limit was hit, and fail as unconfirmed otherwise. Rate limited
+    /// requests wait as long as requested, up to [MAXIMUM_RETRY_AFTER].
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, HttpError> {
+        let request = request.build()?;
+        let idempotent = matches!(
+            *request.method(),
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+        );
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let attempt = request
+                .try_clone()
+                .expect("request bodies are not streamed");
+            let error = match self.client.execute(attempt).await {
+                Ok(response) => match check_response(response).await {
+                    Ok(response) => return Ok(response),
+                    Err(e) => e,
+                },
+                Err(e) => e.into(),
+            };
+
+            let retry = error.was_rejected() || (idempotent && error.is_transient());
+            let wait_too_long = error
+                .retry_after()
+                .is_some_and(|retry_after| retry_after > MAXIMUM_RETRY_AFTER);
+            if !retry && !idempotent && error.is_transient() {
+                return Err(HttpError::Unconfirmed(Box::new(error)));
+            }
+            if !retry || wait_too_long {
+                return Err(error);
+            }
+            if attempts > self.max_retries {
+                return Err(HttpError::RetriesExhausted {
+                    attempts,
+                    last: Box::new(error),
+                });
+            }
+            let delay = error.retry_after().unwrap_or_else(|| backoff(attempts));
+            tracing::debug!(
+                url = %request.url(),
+                "retrying request in {:?} after attempt {} failed: {}",
+                delay,
+                attempts,
+                error
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn parses_retry_after_dates() {
+        let in_a_minute = Utc::now() + chrono::Duration::seconds(61);
+        let header = in_a_minute.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let delay = parse_retry_after(&header).unwrap();
+        assert!(Duration::from_secs(59) <= delay && delay <= Duration::from_secs(61));
+        let past = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(parse_retry_after(past), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn invalid_urls_are_not_transient() {
+        let error = reqwest::Client::new()
+            .get("jira.example.com/rest/api/2/myself")
+            .build()
+            .unwrap_err();
+        assert!(!HttpError::from(error).is_transient());
+    }
+
+    #[test]
+    fn timeouts_of_unconfirmed_requests_are_transient() {
+        let error = HttpError::Unconfirmed(Box::new(HttpError::Timeout));
+        assert!(error.is_transient());
+        assert!(error.is_unconfirmed());
+    }
+}
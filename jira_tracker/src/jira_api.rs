@@ -1,62 +1,98 @@
-use crate::config::AppConfig;
+use std::sync::Arc;
+
+use crate::accounts::AccountId;
+use crate::config::{AccountConfig, AppConfig, Backend, JiraDeployment, Timezone};
+use crate::http::{self, HttpClient, HttpError};
+use crate::worklog::Worklog;
+use crate::worklog_sink::{BookedWorklog, SinkError, SinkFuture, SubmittedWorklog, WorklogSink};
 use axum::http::header::AUTHORIZATION;
-use axum::http::{HeaderMap, HeaderValue};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use serde::Deserialize;
+use chrono::{DateTime, Local, NaiveDate};
+use futures::future::try_join_all;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
 
-const BASE_URI: &str = "https://anevis.atlassian.net/rest/api/latest";
+const JIRA_DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%z";
 
 #[derive(Debug)]
 pub struct JiraApi {
-    client: reqwest::Client,
+    client: HttpClient,
+    base_uri: String,
+    deployment: JiraDeployment,
+    /// Jira answers with the offset of the user profile, days are cut in the configured zone
+    timezone: Timezone,
 }
 
-impl From<&AppConfig> for JiraApi {
-    fn from(value: &AppConfig) -> Self {
-        let auth_string = format!("{}:{}", value.jira_email, value.jira_api_token);
+impl From<(&AppConfig, &AccountConfig)> for JiraApi {
+    fn from((config, value): (&AppConfig, &AccountConfig)) -> Self {
+        let (authorization, api_version) = match value.jira_deployment {
+            JiraDeployment::Cloud => {
+                let email = value
+                    .jira_email
+                    .as_ref()
+                    .expect("JIRA_EMAIL is validated by AppConfig::new");
+                let auth_string = format!("{}:{}", email, value.jira_api_token);
+                (format!("Basic {}", STANDARD.encode(auth_string)), "latest")
+            }
+            JiraDeployment::DataCenter => (format!("Bearer {}", value.jira_api_token), "2"),
+        };
 
-        let mut authorization_value: HeaderValue =
-            format!("Basic {}", STANDARD.encode(auth_string))
-                .parse()
-                .unwrap();
+        let mut authorization_value: HeaderValue = authorization.parse().unwrap();
         authorization_value.set_sensitive(true);
 
         let mut headers = HeaderMap::new();
         headers.insert(AUTHORIZATION, authorization_value);
 
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .unwrap();
+        let client = HttpClient::new(headers, &config.http);
 
-        Self { client }
+        Self {
+            client,
+            base_uri: format!(
+                "{}/rest/api/{}",
+                value.jira_url.trim_end_matches('/'),
+                api_version
+            ),
+            deployment: value.jira_deployment,
+            timezone: config.timezone,
+        }
     }
 }
 
 impl JiraApi {
-    pub async fn get_account_id(&self) -> Result<String, reqwest::Error> {
-        let url = format!("{}/myself", BASE_URI);
-        let response = self.client.get(&url).send().await?;
-        let json = response.json::<serde_json::Value>().await?;
-        let account_id = json["accountId"].as_str().unwrap();
-        Ok(account_id.to_string())
+    /// The account id on Jira Cloud, the user name on Data Center which has no account ids
+    pub async fn get_account_id(&self) -> Result<String, HttpError> {
+        let url = format!("{}/myself", self.base_uri);
+        let response = self.client.send(self.client.get(&url)).await?;
+        let account_id = match self.deployment {
+            JiraDeployment::Cloud => response.json::<CloudUser>().await?.account_id,
+            JiraDeployment::DataCenter => response.json::<DataCenterUser>().await?.name,
+        };
+        Ok(account_id)
     }
 
     pub async fn get_issue_info<K: AsRef<str>>(
         &self,
         issue_key: K,
-    ) -> Result<JiraIssue, reqwest::Error> {
-        let url = format!("{}/issue/{}", BASE_URI, issue_key.as_ref());
-        let response = self
-            .client
-            .get(&url)
-            .query(&[("fields", "summary")])
-            .send()
-            .await?;
-        response.error_for_status()?.json::<JiraIssue>().await
+    ) -> Result<JiraIssue, HttpError> {
+        let url = format!("{}/issue/{}", self.base_uri, issue_key.as_ref());
+        let request = self.client.get(&url).query(&[("fields", "summary")]);
+        let response = self.client.send(request).await?;
+        Ok(response.json::<JiraIssue>().await?)
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CloudUser {
+    account_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataCenterUser {
+    name: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct JiraIssue {
     pub id: String,
@@ -68,3 +104,236 @@ pub struct JiraIssue {
 pub struct IssueFields {
     pub summary: String,
 }
+
+/// Books worklogs natively in Jira instead of Tempo
+pub struct JiraWorklogs {
+    api: Arc<JiraApi>,
+    account_id: AccountId,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JiraWorklogBody {
+    started: String,
+    time_spent_seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+impl From<Worklog> for JiraWorklogBody {
+    fn from(worklog: Worklog) -> Self {
+        Self {
+            started: worklog.start.format(JIRA_DATE_TIME_FORMAT).to_string(),
+            time_spent_seconds: worklog.duration.as_secs(),
+            comment: worklog.description,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JiraWorklog {
+    id: String,
+    issue_id: String,
+    author: JiraUser,
+    started: String,
+    time_spent_seconds: u64,
+    comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JiraUser {
+    account_id: Option<String>,
+    /// Data Center identifies users by name
+    name: Option<String>,
+}
+
+impl JiraUser {
+    fn id(&self) -> Option<&String> {
+        self.account_id.as_ref().or(self.name.as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorklogsPage {
+    start_at: u64,
+    total: u64,
+    worklogs: Vec<JiraWorklog>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchPage {
+    start_at: u64,
+    total: u64,
+    issues: Vec<SearchIssue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchIssue {
+    id: String,
+}
+
+impl JiraWorklogs {
+    pub fn new(api: Arc<JiraApi>, account_id: AccountId) -> Self {
+        Self { api, account_id }
+    }
+
+    async fn submit_worklog(&self, worklog: Worklog) -> Result<SubmittedWorklog, SinkError> {
+        let payload = JiraWorklogBody::from(worklog.clone());
+        let url = format!("{}/issue/{}/worklog", self.api.base_uri, worklog.issue_id);
+        let builder = self.api.client.post(url).json(&payload);
+        let response = self.api.client.send(builder).await?;
+        let created: JiraWorklog = http::processed_json(response).await?;
+        let worklog_id = created.id.parse().map_err(|_| {
+            SinkError::InvalidResponse(format!(
+                "worklog {} was created on issue {} with an unexpected id",
+                created.id, worklog.issue_id
+            ))
+        })?;
+        Ok(SubmittedWorklog {
+            worklog_id,
+            submitted_at: Local::now(),
+            worklog,
+            payload: serde_json::to_value(payload).unwrap(),
+        })
+    }
+
+    /// Ids of the issues the user logged work on within the given days
+    async fn search_issues(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<String>, SinkError> {
+        let jql = format!(
+            "worklogAuthor = currentUser() AND worklogDate >= \"{}\" AND worklogDate <= \"{}\"",
+            from, to
+        );
+        let url = format!("{}/search", self.api.base_uri);
+        let mut issues = Vec::new();
+        loop {
+            let start_at = issues.len().to_string();
+            let query = [
+                ("jql", jql.as_str()),
+                ("fields", "id"),
+                ("startAt", &start_at),
+                ("maxResults", "100"),
+            ];
+            let request = self.api.client.get(&url).query(&query);
+            let response = self.api.client.send(request).await?;
+            let page: SearchPage = response.json().await?;
+            let done =
+                page.issues.is_empty() || page.start_at + page.issues.len() as u64 >= page.total;
+            issues.extend(page.issues.into_iter().map(|issue| issue.id));
+            if done {
+                return Ok(issues);
+            }
+        }
+    }
+
+    async fn issue_worklogs(
+        &self,
+        issue_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<BookedWorklog>, SinkError> {
+        let account_id = self.account_id.get()?;
+        let url = format!("{}/issue/{}/worklog", self.api.base_uri, issue_id);
+        let mut worklogs = Vec::new();
+        let mut start_at = 0;
+        loop {
+            let query = [
+                ("startAt", start_at.to_string()),
+                ("maxResults", "100".to_string()),
+            ];
+            let request = self.api.client.get(&url).query(&query);
+            let response = self.api.client.send(request).await?;
+            let page: WorklogsPage = response.json().await?;
+            start_at = page.start_at + page.worklogs.len() as u64;
+            let done = page.worklogs.is_empty() || start_at >= page.total;
+            for worklog in page.worklogs {
+                if worklog.author.id() == Some(&account_id) {
+                    worklogs.push(self.booked_worklog(worklog)?);
+                }
+            }
+            if done {
+                break;
+            }
+        }
+        Ok(worklogs
+            .into_iter()
+            .filter(|worklog| from <= worklog.start_date && worklog.start_date <= to)
+            .collect())
+    }
+
+    /// Fails if the worklog cannot be read, so duplicates of it are not missed
+    fn booked_worklog(&self, worklog: JiraWorklog) -> Result<BookedWorklog, SinkError> {
+        let invalid = |field: &str, value: &str| {
+            SinkError::InvalidResponse(format!(
+                "worklog {} of issue {} has an unexpected {} `{}`",
+                worklog.id, worklog.issue_id, field, value
+            ))
+        };
+        let started = DateTime::parse_from_str(&worklog.started, JIRA_DATE_TIME_FORMAT)
+            .map_err(|_| invalid("start", &worklog.started))?
+            .with_timezone(&Local);
+        let started = self.api.timezone.convert(started);
+        let worklog_id = worklog.id.parse().map_err(|_| invalid("id", &worklog.id))?;
+        Ok(BookedWorklog {
+            backend: Backend::Jira,
+            worklog_id,
+            issue_id: worklog.issue_id,
+            time_spent_seconds: worklog.time_spent_seconds,
+            start_date: started.date_naive(),
+            start_time: started.time(),
+            description: worklog.comment,
+        })
+    }
+
+    async fn fetch_worklogs(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<BookedWorklog>, SinkError> {
+        let issues = self.search_issues(from, to).await?;
+        let worklogs = try_join_all(
+            issues
+                .iter()
+                .map(|issue_id| self.issue_worklogs(issue_id, from, to)),
+        )
+        .await?;
+        Ok(worklogs.into_iter().flatten().collect())
+    }
+
+    async fn delete_worklog(&self, submitted: &SubmittedWorklog) -> Result<(), SinkError> {
+        let url = format!(
+            "{}/issue/{}/worklog/{}",
+            self.api.base_uri, submitted.worklog.issue_id, submitted.worklog_id
+        );
+        match self.api.client.send(self.api.client.delete(url)).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl WorklogSink for JiraWorklogs {
+    fn request_body(&self, worklog: Worklog) -> Result<serde_json::Value, SinkError> {
+        Ok(serde_json::to_value(JiraWorklogBody::from(worklog)).unwrap())
+    }
+
+    fn submit(&self, worklog: Worklog) -> SinkFuture<'_, SubmittedWorklog> {
+        self.submit_worklog(worklog).boxed()
+    }
+
+    fn get_worklogs(&self, from: NaiveDate, to: NaiveDate) -> SinkFuture<'_, Vec<BookedWorklog>> {
+        self.fetch_worklogs(from, to).boxed()
+    }
+
+    fn delete<'a>(&'a self, submitted: &'a SubmittedWorklog) -> SinkFuture<'a, ()> {
+        self.delete_worklog(submitted).boxed()
+    }
+}
@@ -1,7 +1,6 @@
 #![allow(clippy::new_without_default)]
 extern crate core;
 
-use std::error::Error;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
@@ -9,39 +8,71 @@ use axum::extract::FromRef;
 use axum::ServiceExt;
 use tower_http::normalize_path::NormalizePath;
 
+use crate::accounts::Accounts;
 use crate::app_data::AppData;
-use crate::config::AppConfig;
-use crate::jira_api::JiraApi;
-use crate::tempo_api::TempoApi;
+use crate::config::{AppConfig, Timezone};
+use crate::history::History;
+use crate::outbox::Outbox;
+use crate::schedule::Scheduler;
+use crate::submission::Submitter;
 
+mod accounts;
 mod app_data;
 mod config;
+mod error;
 mod files;
+mod history;
+mod http;
 mod jira_api;
+mod outbox;
+mod schedule;
+mod submission;
 mod tempo_api;
 mod web;
+mod worklog;
+mod worklog_sink;
 
 #[derive(Clone)]
 pub struct AppState {
     data: Arc<AppData>,
-    jira_api: Arc<JiraApi>,
-    tempo_api: Arc<TempoApi>,
+    accounts: Arc<Accounts>,
+    outbox: Arc<Outbox>,
+    history: Arc<History>,
+    submitter: Arc<Submitter>,
+    scheduler: Arc<Scheduler>,
+    timezone: Timezone,
 }
 
 impl AppState {
-    async fn create(config: &AppConfig) -> Result<Self, Box<dyn Error>> {
-        let jira_api: JiraApi = config.into();
-        let jira_account_id = jira_api.get_account_id().await?;
-
-        let data = Arc::new(config.into());
-        let jira_api = Arc::new(jira_api);
-        let tempo_api = Arc::new((config, jira_account_id).into());
-
-        Ok(Self {
+    fn create(config: &AppConfig) -> Self {
+        let data: Arc<AppData> = Arc::new(config.into());
+        let accounts = Arc::new(Accounts::new(config, &data));
+        let outbox = Arc::new(Outbox::from(config));
+        let history = Arc::new(History::from(config));
+        let submitter = Arc::new(Submitter::new(
+            data.clone(),
+            accounts.clone(),
+            outbox.clone(),
+            history.clone(),
+            config,
+        ));
+        let scheduler = Arc::new(Scheduler::from(config));
+
+        Self {
             data,
-            jira_api,
-            tempo_api,
-        })
+            accounts,
+            outbox,
+            history,
+            submitter,
+            scheduler,
+            timezone: config.timezone,
+        }
+    }
+}
+
+impl FromRef<AppState> for Timezone {
+    fn from_ref(input: &AppState) -> Self {
+        input.timezone
     }
 }
 
@@ -51,15 +82,33 @@ impl FromRef<AppState> for Arc<AppData> {
     }
 }
 
-impl FromRef<AppState> for Arc<JiraApi> {
+impl FromRef<AppState> for Arc<Accounts> {
+    fn from_ref(input: &AppState) -> Self {
+        input.accounts.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Outbox> {
+    fn from_ref(input: &AppState) -> Self {
+        input.outbox.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<History> {
+    fn from_ref(input: &AppState) -> Self {
+        input.history.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Submitter> {
     fn from_ref(input: &AppState) -> Self {
-        input.jira_api.clone()
+        input.submitter.clone()
     }
 }
 
-impl FromRef<AppState> for Arc<TempoApi> {
+impl FromRef<AppState> for Arc<Scheduler> {
     fn from_ref(input: &AppState) -> Self {
-        input.tempo_api.clone()
+        input.scheduler.clone()
     }
 }
 
@@ -67,11 +116,36 @@ impl FromRef<AppState> for Arc<TempoApi> {
 async fn main() {
     let logging_layer = config::setup_logging();
 
-    let config = &AppConfig::new();
-    let state: AppState = AppState::create(config).await.unwrap();
+    let config = &match AppConfig::new() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let state = AppState::create(config);
     let cloned_state = state.data.clone();
 
     let _hotwatch = files::watch_file(&config.json_file, move || cloned_state.reload_state());
+    tokio::spawn(
+        state
+            .accounts
+            .clone()
+            .resolve_account_ids(state.data.clone()),
+    );
+    tokio::spawn(state.accounts.clone().resolve_issue_ids(state.data.clone()));
+    tokio::spawn(
+        state
+            .outbox
+            .clone()
+            .process(state.accounts.clone(), state.history.clone()),
+    );
+    tokio::spawn(
+        state
+            .scheduler
+            .clone()
+            .run(state.data.clone(), state.submitter.clone()),
+    );
 
     let router = web::router().layer(logging_layer).with_state(state);
     let app = NormalizePath::trim_trailing_slash(router);
@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Notify};
+
+use domain::TrackerInformation;
+
+use crate::accounts::Accounts;
+use crate::app_data::TrackerError;
+use crate::config::AppConfig;
+use crate::files::StateFile;
+use crate::history::History;
+use crate::worklog::Worklog;
+use crate::worklog_sink::{SinkError, SubmittedWorklog, WorklogSink};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+const MAXIMUM_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+fn backoff(attempts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAXIMUM_BACKOFF)
+}
+
+/// A worklog whose submission failed and is waiting to be retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub id: String,
+    pub tracker_id: String,
+    pub key: String,
+    /// The history entry the worklog is added to once submitted
+    pub history_id: String,
+    pub worklog: Worklog,
+    pub queued_at: DateTime<Local>,
+    pub attempts: u32,
+    pub last_error: String,
+    /// Items which failed permanently are only retried on request
+    pub next_attempt: Option<DateTime<Local>>,
+    /// An attempt might have booked the worklog, so the booked worklogs are checked before
+    /// sending it again
+    #[serde(default)]
+    pub unconfirmed: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InnerOutbox {
+    items: IndexMap<String, OutboxItem>,
+    next_id: u64,
+    /// History entries whose items are not sent while they are being unsubmitted
+    #[serde(skip)]
+    held: HashSet<String>,
+}
+
+impl InnerOutbox {
+    fn enqueue(
+        &mut self,
+        tracker: &TrackerInformation,
+        history_id: &str,
+        worklog: Worklog,
+        error: &SinkError,
+    ) {
+        self.next_id += 1;
+        let id = self.next_id.to_string();
+        let now = Local::now();
+        let item = OutboxItem {
+            id: id.clone(),
+            tracker_id: tracker.id.clone(),
+            key: tracker.key.clone(),
+            history_id: history_id.to_string(),
+            worklog,
+            queued_at: now,
+            attempts: 1,
+            last_error: error.to_string(),
+            next_attempt: Some(now + backoff(1)),
+            unconfirmed: error.is_unconfirmed(),
+        };
+        self.items.insert(id, item);
+    }
+
+    fn get(&self, id: &str) -> Result<OutboxItem, TrackerError> {
+        self.items
+            .get(id)
+            .cloned()
+            .ok_or(TrackerError::NotFoundError)
+    }
+
+    fn retry(&mut self, id: &str) -> Result<OutboxItem, TrackerError> {
+        let item = self.items.get_mut(id).ok_or(TrackerError::NotFoundError)?;
+        item.next_attempt = Some(Local::now());
+        Ok(item.clone())
+    }
+
+    fn retry_all(&mut self) {
+        let now = Local::now();
+        for item in self.items.values_mut() {
+            item.next_attempt = Some(now);
+        }
+    }
+
+    fn cancel(&mut self, id: &str) -> Result<OutboxItem, TrackerError> {
+        self.items
+            .shift_remove(id)
+            .ok_or(TrackerError::NotFoundError)
+    }
+
+    fn cancel_history(&mut self, history_id: &str) -> Vec<OutboxItem> {
+        let ids: Vec<_> = self
+            .items
+            .values()
+            .filter(|item| item.history_id == history_id)
+            .map(|item| item.id.clone())
+            .collect();
+        self.held.remove(history_id);
+        ids.iter()
+            .filter_map(|id| self.items.shift_remove(id))
+            .collect()
+    }
+
+    fn is_held(&self, item: &OutboxItem) -> bool {
+        self.held.contains(&item.history_id)
+    }
+
+    fn next_attempt(&self) -> Option<DateTime<Local>> {
+        self.items
+            .values()
+            .filter(|item| !self.is_held(item))
+            .filter_map(|item| item.next_attempt)
+            .min()
+    }
+
+    fn due(&self) -> Vec<OutboxItem> {
+        let now = Local::now();
+        self.items
+            .values()
+            .filter(|item| !self.is_held(item))
+            .filter(|item| item.next_attempt.filter(|next| *next <= now).is_some())
+            .cloned()
+            .collect()
+    }
+
+    fn complete(&mut self, id: &str, result: Result<SubmittedWorklog, SinkError>) {
+        let Err(error) = result else {
+            self.items.shift_remove(id);
+            return;
+        };
+        if let Some(item) = self.items.get_mut(id) {
+            item.attempts += 1;
+            item.last_error = error.to_string();
+            item.unconfirmed |= error.is_unconfirmed();
+            item.next_attempt = error
+                .is_transient()
+                .then(|| Local::now() + backoff(item.attempts));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Outbox {
+    state: StateFile<InnerOutbox>,
+    changed: Notify,
+    /// The history entry of the item which is being sent
+    sending: watch::Sender<Option<String>>,
+}
+
+impl Outbox {
+    fn reading<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&InnerOutbox) -> T,
+    {
+        self.state.reading(f)
+    }
+
+    fn writing<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut InnerOutbox) -> T,
+    {
+        let result = self.state.writing(f);
+        self.changed.notify_one();
+        result
+    }
+
+    pub fn enqueue(
+        &self,
+        tracker: &TrackerInformation,
+        history_id: &str,
+        worklog: Worklog,
+        error: &SinkError,
+    ) {
+        self.writing(|a| a.enqueue(tracker, history_id, worklog, error))
+    }
+
+    pub fn list(&self) -> Vec<OutboxItem> {
+        self.reading(|a| a.items.values().cloned().collect())
+    }
+
+    pub fn get(&self, id: &str) -> Result<OutboxItem, TrackerError> {
+        self.reading(|a| a.get(id))
+    }
+
+    pub fn retry(&self, id: &str) -> Result<OutboxItem, TrackerError> {
+        self.writing(|a| a.retry(id))
+    }
+
+    pub fn retry_all(&self) {
+        self.writing(|a| a.retry_all())
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<OutboxItem, TrackerError> {
+        self.writing(|a| a.cancel(id))
+    }
+
+    /// Drops all items which would be added to the given history entry
+    pub fn cancel_history(&self, history_id: &str) -> Vec<OutboxItem> {
+        self.writing(|a| a.cancel_history(history_id))
+    }
+
+    /// Stops sending the items of a history entry and waits until none of them is being sent
+    pub async fn hold_history(&self, history_id: &str) {
+        self.writing(|a| a.held.insert(history_id.to_string()));
+        let mut sending = self.sending.subscribe();
+        let _ = sending
+            .wait_for(|sending| sending.as_deref() != Some(history_id))
+            .await;
+    }
+
+    /// Sends the items of a history entry again after [Outbox::hold_history]
+    pub fn release_history(&self, history_id: &str) {
+        self.writing(|a| a.held.remove(history_id));
+    }
+
+    /// Submits the worklog of the item, unless an unconfirmed attempt has already booked it
+    async fn send(
+        sink: &dyn WorklogSink,
+        item: &OutboxItem,
+    ) -> Result<SubmittedWorklog, SinkError> {
+        if item.unconfirmed {
+            let day = item.worklog.start.date_naive();
+            let booked = sink.get_worklogs(day, day).await?;
+            if let Some(booked) = booked.iter().find(|booked| booked.matches(&item.worklog)) {
+                tracing::info!(
+                    id = item.id,
+                    key = item.key,
+                    "queued worklog was already booked"
+                );
+                return Ok(SubmittedWorklog {
+                    worklog_id: booked.worklog_id,
+                    submitted_at: Local::now(),
+                    worklog: item.worklog.clone(),
+                    payload: sink.request_body(item.worklog.clone())?,
+                });
+            }
+        }
+        sink.submit(item.worklog.clone()).await
+    }
+
+    /// Submits due items until the task is aborted, waking up early whenever the outbox changes
+    pub async fn process(self: Arc<Self>, accounts: Arc<Accounts>, history: Arc<History>) {
+        loop {
+            let next_attempt = self.reading(|a| a.next_attempt());
+            let Some(next_attempt) = next_attempt else {
+                self.changed.notified().await;
+                continue;
+            };
+            let wait = (next_attempt - Local::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.changed.notified() => continue,
+            }
+
+            for item in self.reading(|a| a.due()) {
+                let held = self.reading(|a| {
+                    let held = a.is_held(&item) || !a.items.contains_key(&item.id);
+                    if !held {
+                        self.sending.send_replace(Some(item.history_id.clone()));
+                    }
+                    held
+                });
+                if held {
+                    continue;
+                }
+                let result = match accounts.worklog_sink(&item.worklog) {
+                    Ok(sink) => Self::send(sink, &item).await,
+                    Err(e) => Err(e),
+                };
+                match &result {
+                    Ok(submitted) => {
+                        tracing::info!(id = item.id, key = item.key, "submitted queued worklog");
+                        history.add_worklog(&item.history_id, submitted.clone());
+                    }
+                    Err(e) => tracing::warn!(
+                        id = item.id,
+                        key = item.key,
+                        "retrying queued worklog failed: {}",
+                        e
+                    ),
+                }
+                self.writing(|a| a.complete(&item.id, result));
+                self.sending.send_replace(None);
+            }
+        }
+    }
+}
+
+impl From<&AppConfig> for Outbox {
+    fn from(config: &AppConfig) -> Self {
+        Outbox {
+            state: StateFile::load(config.json_file.with_extension("outbox.json")),
+            changed: Notify::new(),
+            sending: watch::channel(None).0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(id: &str, history_id: &str) -> OutboxItem {
+        let worklog = json!({
+            "issue_id": "10001",
+            "start": "2024-03-04T09:00:00+01:00",
+            "duration": "1h",
+            "description": null,
+        });
+        OutboxItem {
+            id: id.to_string(),
+            tracker_id: "1".to_string(),
+            key: "ABC-1".to_string(),
+            history_id: history_id.to_string(),
+            worklog: serde_json::from_value(worklog).unwrap(),
+            queued_at: Local::now(),
+            attempts: 1,
+            last_error: "upstream unavailable".to_string(),
+            next_attempt: Some(Local::now()),
+            unconfirmed: false,
+        }
+    }
+
+    fn outbox(test: &str) -> Outbox {
+        let file = format!("{}-{}.outbox.json", test, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+        let outbox = Outbox {
+            state: StateFile::load(path),
+            changed: Notify::new(),
+            sending: watch::channel(None).0,
+        };
+        outbox.writing(|a| {
+            a.items.insert("1".to_string(), item("1", "h1"));
+            a.items.insert("2".to_string(), item("2", "h2"));
+        });
+        outbox
+    }
+
+    fn due(outbox: &Outbox) -> Vec<String> {
+        outbox.reading(|a| a.due().into_iter().map(|item| item.id).collect())
+    }
+
+    #[tokio::test]
+    async fn held_items_are_not_sent_until_released() {
+        let outbox = outbox("held_items_are_not_sent_until_released");
+        outbox.hold_history("h1").await;
+        assert_eq!(due(&outbox), vec!["2"]);
+
+        outbox.release_history("h1");
+        assert_eq!(due(&outbox), vec!["1", "2"]);
+
+        outbox.hold_history("h1").await;
+        let cancelled = outbox.cancel_history("h1");
+        assert_eq!(cancelled.len(), 1);
+        assert!(outbox.reading(|a| a.held.is_empty()));
+        assert_eq!(due(&outbox), vec!["2"]);
+    }
+
+    #[tokio::test]
+    async fn holding_waits_for_the_item_being_sent() {
+        let outbox = outbox("holding_waits_for_the_item_being_sent");
+        outbox.sending.send_replace(Some("h1".to_string()));
+        let hold = tokio::time::timeout(Duration::from_millis(50), outbox.hold_history("h1"));
+        assert!(hold.await.is_err());
+
+        outbox.sending.send_replace(Some("h2".to_string()));
+        let hold = tokio::time::timeout(Duration::from_millis(50), outbox.hold_history("h1"));
+        assert!(hold.await.is_ok());
+    }
+}
@@ -0,0 +1,322 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveTime, TimeZone, Weekday};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::app_data::AppData;
+use crate::config::{AppConfig, Timezone};
+use crate::files::StateFile;
+use crate::submission::{
+    DuplicateWorklog, SubmissionReport, SubmitError, SubmitOptions, SubmitSelection, Submitter,
+};
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Number of scheduled runs which are kept for inspection
+const KEPT_RUNS: usize = 100;
+
+/// The schedule is checked against the wall clock at least this often, so runs are not delayed
+/// by a suspended system
+const MAXIMUM_SLEEP: Duration = Duration::from_secs(60);
+
+/// Days of the week and a time of day, e.g. `weekdays 18:00`, `daily 17:30` or `mon,wed,fri 12:00`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    days: Vec<Weekday>,
+    time: NaiveTime,
+}
+
+impl Schedule {
+    /// The first scheduled point in time after the given one.
+    ///
+    /// Times skipped by a daylight saving time change run an hour later, repeated times run once.
+    pub fn next_run<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        (0..=7)
+            .filter_map(|offset| after.date_naive().checked_add_days(Days::new(offset)))
+            .filter(|date| self.days.contains(&date.weekday()))
+            .filter_map(|date| {
+                let time = date.and_time(self.time);
+                timezone.from_local_datetime(&time).earliest().or_else(|| {
+                    let shifted = time + chrono::Duration::hours(1);
+                    timezone.from_local_datetime(&shifted).earliest()
+                })
+            })
+            .find(|time| *time > after)
+    }
+
+    /// The first scheduled point in time after the given one, evaluated in the given time zone
+    pub fn next_run_in(
+        &self,
+        after: DateTime<Local>,
+        timezone: Timezone,
+    ) -> Option<DateTime<Local>> {
+        match timezone {
+            Timezone::System => self.next_run(after),
+            Timezone::Named(timezone) => self
+                .next_run(after.with_timezone(&timezone))
+                .map(|run| run.with_timezone(&Local)),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (days, time) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected days and time of day, got `{}`", s))?;
+        let days = match days.to_lowercase().as_str() {
+            "daily" => WEEKDAYS.to_vec(),
+            "weekdays" => WEEKDAYS[..5].to_vec(),
+            "weekends" => WEEKDAYS[5..].to_vec(),
+            list => list
+                .split(',')
+                .map(|day| {
+                    Weekday::from_str(day.trim()).map_err(|_| format!("invalid day `{}`", day))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        let time = NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|e| format!("invalid time of day `{}`: {}", time, e))?;
+        Ok(Schedule { days, time })
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let days: Vec<_> = self
+            .days
+            .iter()
+            .map(|day| day.to_string().to_lowercase())
+            .collect();
+        write!(f, "{} {}", days.join(","), self.time.format("%H:%M"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        string.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Schedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The result of a submission started by the schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledRun {
+    pub started_at: DateTime<Local>,
+    #[serde(flatten)]
+    pub outcome: RunOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RunOutcome {
+    Completed { report: SubmissionReport },
+    Refused { duplicates: Vec<DuplicateWorklog> },
+    Failed { error: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleStatus {
+    pub schedule: Option<Schedule>,
+    pub next_run: Option<DateTime<Local>>,
+    pub runs: Vec<ScheduledRun>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InnerScheduler {
+    runs: Vec<ScheduledRun>,
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    schedule: Option<Schedule>,
+    /// The time zone the schedule is evaluated in
+    timezone: Timezone,
+    state: StateFile<InnerScheduler>,
+}
+
+impl Scheduler {
+    fn record(&self, run: ScheduledRun) {
+        self.state.writing(|a| {
+            a.runs.push(run);
+            let excess = a.runs.len().saturating_sub(KEPT_RUNS);
+            a.runs.drain(..excess);
+        })
+    }
+
+    pub fn status(&self) -> ScheduleStatus {
+        ScheduleStatus {
+            schedule: self.schedule.clone(),
+            next_run: self
+                .schedule
+                .as_ref()
+                .and_then(|schedule| schedule.next_run_in(Local::now(), self.timezone)),
+            runs: self.state.reading(|a| a.runs.clone()),
+        }
+    }
+
+    /// Pauses the running tracker and submits all trackers whenever the schedule is due.
+    ///
+    /// Runs missed while the daemon was not running are not caught up on.
+    pub async fn run(self: Arc<Self>, data: Arc<AppData>, submitter: Arc<Submitter>) {
+        let Some(schedule) = self.schedule.clone() else {
+            return;
+        };
+        let mut after = Local::now();
+        while let Some(next_run) = schedule.next_run_in(after, self.timezone) {
+            loop {
+                let wait = (next_run - Local::now()).to_std().unwrap_or_default();
+                if wait.is_zero() {
+                    break;
+                }
+                tokio::time::sleep(wait.min(MAXIMUM_SLEEP)).await;
+            }
+            after = next_run;
+
+            tracing::info!("starting scheduled submission");
+            let started_at = Local::now();
+            data.pause();
+            let outcome = match submitter
+                .submit(SubmitSelection::default(), SubmitOptions::default())
+                .await
+            {
+                Ok(report) => RunOutcome::Completed { report },
+                Err(SubmitError::Duplicates(duplicates)) => {
+                    tracing::warn!("scheduled submission refused because of duplicates");
+                    RunOutcome::Refused { duplicates }
+                }
+                Err(SubmitError::Tracker(e)) => RunOutcome::Failed {
+                    error: e.to_string(),
+                },
+                Err(SubmitError::UnknownTracker(id)) => RunOutcome::Failed {
+                    error: format!("tracker {} not found", id),
+                },
+                Err(SubmitError::DuplicateCheck(e)) => RunOutcome::Failed {
+                    error: format!("duplicate check failed: {}", e),
+                },
+            };
+            self.record(ScheduledRun {
+                started_at,
+                outcome,
+            });
+        }
+    }
+}
+
+impl From<&AppConfig> for Scheduler {
+    fn from(config: &AppConfig) -> Self {
+        Scheduler {
+            schedule: config.submit_schedule.clone(),
+            timezone: config.timezone,
+            state: StateFile::load(config.json_file.with_extension("schedule.json")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn schedule(s: &str) -> Schedule {
+        s.parse().unwrap()
+    }
+
+    fn berlin(date: (i32, u32, u32), time: (u32, u32)) -> DateTime<chrono_tz::Tz> {
+        let date = NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap();
+        let time = date.and_hms_opt(time.0, time.1, 0).unwrap();
+        Berlin.from_local_datetime(&time).earliest().unwrap()
+    }
+
+    #[test]
+    fn parses_day_groups_and_lists() {
+        assert_eq!(schedule("daily 17:30").days, WEEKDAYS.to_vec());
+        assert_eq!(schedule("Weekdays 18:00").days, WEEKDAYS[..5].to_vec());
+        assert_eq!(schedule("weekends 09:00").days, WEEKDAYS[5..].to_vec());
+        let list = schedule(" mon,Wed,fri  12:00 ");
+        assert_eq!(list.days, vec![Weekday::Mon, Weekday::Wed, Weekday::Fri]);
+        assert_eq!(list.time, NaiveTime::from_hms_opt(12, 0, 0).unwrap());
+        assert_eq!(list.to_string(), "mon,wed,fri 12:00");
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        assert!("daily".parse::<Schedule>().is_err());
+        assert!("someday 12:00".parse::<Schedule>().is_err());
+        assert!("daily 25:00".parse::<Schedule>().is_err());
+        assert!("mon,,fri 12:00".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn next_run_is_strictly_after() {
+        let daily = schedule("daily 18:00");
+        let run = daily.next_run(berlin((2024, 5, 6), (17, 0)));
+        assert_eq!(run, Some(berlin((2024, 5, 6), (18, 0))));
+        let run = daily.next_run(berlin((2024, 5, 6), (18, 0)));
+        assert_eq!(run, Some(berlin((2024, 5, 7), (18, 0))));
+    }
+
+    #[test]
+    fn next_run_skips_days_not_scheduled() {
+        // 2024-05-10 is a friday
+        let run = schedule("weekdays 18:00").next_run(berlin((2024, 5, 10), (19, 0)));
+        assert_eq!(run, Some(berlin((2024, 5, 13), (18, 0))));
+        let run = schedule("fri 18:00").next_run(berlin((2024, 5, 10), (19, 0)));
+        assert_eq!(run, Some(berlin((2024, 5, 17), (18, 0))));
+    }
+
+    #[test]
+    fn next_run_in_daylight_saving_time_gap() {
+        // clocks are put forward from 02:00 to 03:00 on 2024-03-31
+        let run = schedule("daily 02:30").next_run(berlin((2024, 3, 31), (1, 0)));
+        assert_eq!(run, Some(berlin((2024, 3, 31), (3, 30))));
+    }
+
+    #[test]
+    fn next_run_in_repeated_hour_runs_once() {
+        // clocks are put back from 03:00 to 02:00 on 2024-10-27
+        let daily = schedule("daily 02:30");
+        let first = daily.next_run(berlin((2024, 10, 27), (1, 0))).unwrap();
+        assert_eq!(first.offset().to_string(), "CEST");
+        let next = daily.next_run(first).unwrap();
+        assert_eq!(next, berlin((2024, 10, 28), (2, 30)));
+    }
+
+    #[test]
+    fn next_run_is_evaluated_in_the_configured_time_zone() {
+        let after = berlin((2024, 5, 6), (12, 0)).with_timezone(&Local);
+        let run = schedule("daily 18:00").next_run_in(after, Timezone::Named(Berlin));
+        assert_eq!(
+            run,
+            Some(berlin((2024, 5, 6), (18, 0)).with_timezone(&Local))
+        );
+        let run = schedule("daily 18:00").next_run_in(after, Timezone::Named(chrono_tz::UTC));
+        assert_eq!(
+            run,
+            Some(berlin((2024, 5, 6), (20, 0)).with_timezone(&Local))
+        );
+    }
+}
@@ -0,0 +1,1055 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDate;
+use futures::future::{join_all, try_join_all};
+use humantime_serde::re::humantime::format_duration;
+use serde::{Deserialize, Serialize};
+
+use domain::{TempoAttributes, TrackerInformation};
+
+use crate::accounts::{AccountError, Accounts};
+use crate::app_data::{AppData, TrackerError};
+use crate::config::{AppConfig, Backend, SubmitPolicy, Timezone, WorklogStart};
+use crate::error::ApiError;
+use crate::history::{History, HistoryEntry};
+use crate::outbox::Outbox;
+use crate::worklog::{self, Worklog};
+use crate::worklog_sink::{BookedWorklog, LockedPeriod, SinkError};
+
+pub struct Submitter {
+    data: Arc<AppData>,
+    accounts: Arc<Accounts>,
+    outbox: Arc<Outbox>,
+    history: Arc<History>,
+    worklog_start: WorklogStart,
+    timezone: Timezone,
+    policy: SubmitPolicy,
+    tempo_defaults: HashMap<String, TempoAttributes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmissionReport {
+    pub trackers: Vec<TrackerSubmission>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackerSubmission {
+    pub id: String,
+    pub key: String,
+    #[serde(flatten)]
+    pub outcome: SubmissionOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SubmissionOutcome {
+    Submitted {
+        worklogs: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        history_id: Option<String>,
+    },
+    Queued {
+        worklogs: usize,
+        queued: usize,
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        history_id: Option<String>,
+    },
+    Skipped {
+        reason: String,
+    },
+    Failed {
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        history_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackerPreview {
+    pub id: String,
+    pub key: String,
+    #[serde(flatten)]
+    pub plan: PreviewPlan,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PreviewPlan {
+    Submit {
+        worklogs: Vec<serde_json::Value>,
+        /// Worklogs which would not be submitted together with the reason
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        rejected: Vec<String>,
+        /// Why some of the worklogs might still fail
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        notes: Vec<String>,
+        #[serde(with = "humantime_serde")]
+        leftover: Duration,
+    },
+    Skip {
+        reason: String,
+        #[serde(with = "humantime_serde")]
+        leftover: Duration,
+    },
+}
+
+/// Time already booked in Tempo and time not yet submitted on a single day
+#[derive(Debug, Serialize)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    #[serde(with = "humantime_serde")]
+    pub booked: Duration,
+    #[serde(with = "humantime_serde")]
+    pub pending: Duration,
+    #[serde(with = "humantime_serde")]
+    pub total: Duration,
+}
+
+/// The day summaries, which lack the booked time of the accounts which could not be queried
+#[derive(Debug, Serialize)]
+pub struct DaysReport {
+    pub days: Vec<DaySummary>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<AccountError>,
+}
+
+/// A planned worklog which looks like it has already been booked in Tempo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateWorklog {
+    pub id: String,
+    pub key: String,
+    pub date: NaiveDate,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    pub account: String,
+    pub backend: Backend,
+    pub worklog_id: u64,
+    #[serde(with = "humantime_serde")]
+    pub booked_duration: Duration,
+}
+
+#[derive(Debug)]
+pub enum SubmitError {
+    Tracker(TrackerError),
+    Duplicates(Vec<DuplicateWorklog>),
+    /// No tracker has the selected id or key
+    UnknownTracker(String),
+    /// The booked worklogs could not be fetched to check for duplicates
+    DuplicateCheck(SinkError),
+}
+
+impl From<TrackerError> for SubmitError {
+    fn from(value: TrackerError) -> Self {
+        SubmitError::Tracker(value)
+    }
+}
+
+impl From<SubmitError> for ApiError {
+    fn from(value: SubmitError) -> Self {
+        match value {
+            SubmitError::Tracker(e) => e.into(),
+            SubmitError::Duplicates(duplicates) => ApiError::new(
+                StatusCode::CONFLICT,
+                "duplicates",
+                "probable duplicates are already booked, submit with force=true to book anyway",
+            )
+            .detail("duplicates", duplicates),
+            SubmitError::UnknownTracker(id) => ApiError::from(TrackerError::NotFoundError)
+                .key(id)
+                .field("trackers"),
+            SubmitError::DuplicateCheck(e) => {
+                ApiError::from(e).detail("failed_check", "duplicates")
+            }
+        }
+    }
+}
+
+impl IntoResponse for SubmitError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// The account and backend a worklog is booked in
+type SinkKey = (Option<String>, Backend);
+
+/// Locked periods of each account and backend, or why they could not be fetched
+type LockedPeriods = HashMap<SinkKey, Result<Vec<LockedPeriod>, SinkError>>;
+
+fn sink_key(worklog: &Worklog) -> SinkKey {
+    (worklog.account.clone(), worklog.backend)
+}
+
+/// The days of the planned worklogs per account and backend
+fn planned_ranges(
+    plans: &[(TrackerInformation, Plan)],
+) -> HashMap<SinkKey, (NaiveDate, NaiveDate)> {
+    let mut ranges: HashMap<SinkKey, (NaiveDate, NaiveDate)> = HashMap::new();
+    for worklog in plans.iter().flat_map(|(_, plan)| &plan.worklogs) {
+        let day = worklog.start.date_naive();
+        ranges
+            .entry(sink_key(worklog))
+            .and_modify(|(from, to)| {
+                *from = (*from).min(day);
+                *to = (*to).max(day);
+            })
+            .or_insert((day, day));
+    }
+    ranges
+}
+
+/// The worklogs of the tracker reduced by the time which has already been submitted
+fn pending_worklogs(
+    tracker: &TrackerInformation,
+    worklog_start: WorklogStart,
+    timezone: Timezone,
+) -> Vec<Worklog> {
+    worklog::split_by_day(tracker, worklog_start, timezone)
+        .into_iter()
+        .filter_map(|worklog| {
+            let submitted = tracker
+                .submitted_seconds
+                .get(&worklog.tracked_day())
+                .copied()
+                .unwrap_or_default();
+            let pending = worklog
+                .tracked
+                .saturating_sub(Duration::from_secs(submitted));
+            (!pending.is_zero()).then_some(Worklog {
+                duration: pending,
+                tracked: pending,
+                ..worklog
+            })
+        })
+        .collect()
+}
+
+/// Rejects worklogs within locked periods or moves them to the first open day after them.
+///
+/// Worklogs whose locked periods could not be fetched are kept with a note, so they are submitted
+/// or queued like all other worklogs and only fail if the backend refuses them.
+fn apply_locked_periods(
+    plan: &mut Plan,
+    locked: &LockedPeriods,
+    move_to_open: bool,
+    timezone: Timezone,
+) {
+    let mut worklogs = Vec::new();
+    for mut worklog in plan.worklogs.drain(..) {
+        let sink_locked = match locked.get(&sink_key(&worklog)) {
+            Some(Ok(locked)) => locked.as_slice(),
+            Some(Err(e)) => {
+                let note = format!(
+                    "{}: locked periods could not be checked: {}",
+                    worklog.start.date_naive(),
+                    e
+                );
+                plan.notes.push(note);
+                worklogs.push(worklog);
+                continue;
+            }
+            None => &[],
+        };
+        let find_locked = |day: NaiveDate| {
+            sink_locked
+                .iter()
+                .find(|locked| locked.period.contains(day))
+        };
+        let Some(lock) = find_locked(worklog.start.date_naive()) else {
+            worklogs.push(worklog);
+            continue;
+        };
+        if !move_to_open {
+            let reason = format!(
+                "period {} to {} is {}",
+                lock.period.from, lock.period.to, lock.status
+            );
+            plan.rejected.push((worklog, reason));
+            continue;
+        }
+        let mut day = lock.period.to;
+        while let Some(lock) = find_locked(day) {
+            day = lock.period.to.succ_opt().unwrap_or(lock.period.to);
+        }
+        worklog.move_to(day, timezone);
+        worklogs.push(worklog);
+    }
+    plan.worklogs = worklogs;
+}
+
+/// Trackers created while Jira was not reachable are kept until their issue is resolved, unless
+/// resolving it failed permanently
+fn unresolved_reason(tracker: &TrackerInformation) -> String {
+    tracker
+        .issue_error
+        .clone()
+        .unwrap_or_else(|| format!("issue {} has not been resolved in Jira yet", tracker.key))
+}
+
+#[derive(Debug)]
+pub enum UnsubmitError {
+    Tracker(TrackerError),
+    Sink(SinkError),
+}
+
+impl From<TrackerError> for UnsubmitError {
+    fn from(value: TrackerError) -> Self {
+        UnsubmitError::Tracker(value)
+    }
+}
+
+impl From<UnsubmitError> for ApiError {
+    fn from(value: UnsubmitError) -> Self {
+        match value {
+            UnsubmitError::Tracker(e) => e.into(),
+            UnsubmitError::Sink(e) => e.into(),
+        }
+    }
+}
+
+impl IntoResponse for UnsubmitError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SubmitOptions {
+    /// Submit even if probable duplicates are found or booked worklogs cannot be checked
+    #[serde(default)]
+    pub force: bool,
+    /// Move worklogs of locked periods to the first open day instead of rejecting them
+    #[serde(default)]
+    pub move_to_open: bool,
+}
+
+/// Restricts a submission to some of the trackers, all trackers are submitted by default
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubmitSelection {
+    /// Ids or keys of the trackers to submit
+    #[serde(default)]
+    pub trackers: Vec<String>,
+    /// Only submit the time tracked before this day, later time is kept in the trackers
+    pub before: Option<NaiveDate>,
+}
+
+impl SubmitSelection {
+    fn selects(&self, tracker: &TrackerInformation) -> bool {
+        self.trackers.is_empty()
+            || self
+                .trackers
+                .iter()
+                .any(|id| *id == tracker.id || *id == tracker.key)
+    }
+}
+
+struct Plan {
+    worklogs: Vec<Worklog>,
+    /// Worklogs which cannot be submitted together with the reason
+    rejected: Vec<(Worklog, String)>,
+    /// Time which is not submitted because of rounding down or the minimum duration
+    leftover: Duration,
+    /// Whether time tracked on or after the selected day stays in the tracker
+    kept: bool,
+    /// Why some of the worklogs might still fail, e.g. because their locked periods are unknown
+    notes: Vec<String>,
+}
+
+impl Submitter {
+    pub fn new(
+        data: Arc<AppData>,
+        accounts: Arc<Accounts>,
+        outbox: Arc<Outbox>,
+        history: Arc<History>,
+        config: &AppConfig,
+    ) -> Self {
+        Self {
+            data,
+            accounts,
+            outbox,
+            history,
+            worklog_start: config.worklog_start,
+            timezone: config.timezone,
+            policy: config.submit_policy.clone(),
+            tempo_defaults: config.tempo_defaults.clone(),
+        }
+    }
+
+    /// Tempo attributes of the tracker, falling back to the defaults of its project
+    fn tempo_attributes(&self, tracker: &TrackerInformation) -> TempoAttributes {
+        let project = tracker.key.split('-').next().unwrap_or_default();
+        match self.tempo_defaults.get(project) {
+            Some(defaults) => tracker.tempo.with_defaults(defaults),
+            None => tracker.tempo.clone(),
+        }
+    }
+
+    /// Determines the time of each day before the given day which has not been submitted yet and
+    /// applies the submit policy to it, failing if the account of the tracker is not configured
+    fn plan(
+        &self,
+        tracker: &TrackerInformation,
+        before: Option<NaiveDate>,
+    ) -> Result<Plan, TrackerError> {
+        let tempo = self.tempo_attributes(tracker);
+        let account = self.accounts.tracker_account(tracker);
+        let backend = self.accounts.get(&account)?.sinks.backend(&tracker.key);
+        let mut worklogs = Vec::new();
+        let mut leftover = Duration::ZERO;
+        let (pending, later): (Vec<_>, Vec<_>) = self
+            .pending_worklogs(tracker)
+            .into_iter()
+            .partition(|worklog| before.is_none_or(|before| worklog.tracked_day() < before));
+        for worklog in pending {
+            let rounded = self.policy.round(worklog.duration);
+            if rounded.is_zero() || rounded < self.policy.minimum {
+                leftover += worklog.duration;
+                continue;
+            }
+            leftover += worklog.duration.saturating_sub(rounded);
+            worklogs.push(Worklog {
+                duration: rounded,
+                tempo: tempo.clone(),
+                backend,
+                account: Some(account.clone()),
+                ..worklog
+            });
+        }
+        Ok(Plan {
+            worklogs,
+            rejected: Vec::new(),
+            leftover,
+            kept: !later.is_empty(),
+            notes: Vec::new(),
+        })
+    }
+
+    fn pending_worklogs(&self, tracker: &TrackerInformation) -> Vec<Worklog> {
+        pending_worklogs(tracker, self.worklog_start, self.timezone)
+    }
+
+    /// Fetches the locked periods of the planned days per account and backend
+    async fn locked_periods(&self, plans: &[(TrackerInformation, Plan)]) -> LockedPeriods {
+        let queries =
+            planned_ranges(plans)
+                .into_iter()
+                .map(|((account, backend), (from, to))| async move {
+                    let locked = match self.accounts.sink(account.as_deref(), backend) {
+                        Ok(sink) => sink.get_locked_periods(from, to).await,
+                        Err(e) => Err(e),
+                    };
+                    ((account, backend), locked)
+                });
+        join_all(queries).await.into_iter().collect()
+    }
+
+    /// The tracked time a submitted worklog accounts for, time lost by rounding down stays in the
+    /// tracker if it is carried over
+    fn handled_time(&self, worklog: &Worklog) -> (NaiveDate, Duration) {
+        let (day, tracked) = worklog.tracked_time();
+        if self.policy.carry_over {
+            (day, tracked.min(worklog.duration))
+        } else {
+            (day, tracked)
+        }
+    }
+
+    fn skip_reason(&self) -> String {
+        format!(
+            "no worklog reaches the minimum duration of {}",
+            format_duration(self.policy.minimum)
+        )
+    }
+
+    /// Submits the planned worklogs of a tracker.
+    ///
+    /// Submitted worklogs are archived in the history. Worklogs failing for transient reasons are
+    /// handed over to the outbox, which retries them in the background. Trackers whose worklogs
+    /// were all submitted or queued are removed, as well as skipped trackers, unless their
+    /// leftover time is carried over. If some days of a tracker fail permanently or are not
+    /// selected, the other ones are marked as submitted, so they are not sent again on the next
+    /// try.
+    async fn submit_tracker(&self, tracker: TrackerInformation, plan: Plan) -> TrackerSubmission {
+        let outcome = if plan.worklogs.is_empty() && plan.rejected.is_empty() {
+            if !self.policy.carry_over && !plan.kept {
+                let _ = self.data.remove(&tracker.id);
+            }
+            SubmissionOutcome::Skipped {
+                reason: self.skip_reason(),
+            }
+        } else {
+            self.submit_worklogs(&tracker, plan).await
+        };
+        TrackerSubmission {
+            id: tracker.id,
+            key: tracker.key,
+            outcome,
+        }
+    }
+
+    async fn submit_worklogs(&self, tracker: &TrackerInformation, plan: Plan) -> SubmissionOutcome {
+        for note in &plan.notes {
+            tracing::warn!(id = tracker.id, key = tracker.key, "{}", note);
+        }
+        let results = join_all(plan.worklogs.into_iter().map(|worklog| async {
+            let result = match self.accounts.worklog_sink(&worklog) {
+                Ok(sink) => sink.submit(worklog.clone()).await,
+                Err(e) => Err(e),
+            };
+            (worklog, result)
+        }))
+        .await;
+
+        let mut submitted = Vec::new();
+        let mut queued = Vec::new();
+        let mut errors: Vec<_> = plan
+            .rejected
+            .iter()
+            .map(|(worklog, reason)| format!("{}: {}", worklog.start.date_naive(), reason))
+            .collect();
+        for (worklog, result) in results {
+            match result {
+                Ok(submitted_worklog) => submitted.push(submitted_worklog),
+                Err(e) if e.is_transient() => queued.push((worklog, e)),
+                Err(e) => errors.push(format!("{}: {}", worklog.start.date_naive(), e)),
+            }
+        }
+
+        let handled_days: Vec<_> = submitted
+            .iter()
+            .map(|submitted| &submitted.worklog)
+            .chain(queued.iter().map(|(worklog, _)| worklog))
+            .map(|worklog| self.handled_time(worklog))
+            .collect();
+        let worklogs = submitted.len();
+        let history_id =
+            (!handled_days.is_empty()).then(|| self.history.create(tracker.clone(), submitted));
+
+        let mut queued_errors = Vec::new();
+        for (worklog, e) in queued {
+            queued_errors.push(format!("{}: {}", worklog.start.date_naive(), e));
+            let history_id = history_id.as_deref().unwrap();
+            self.outbox.enqueue(tracker, history_id, worklog, &e);
+        }
+
+        if !errors.is_empty() || plan.kept {
+            let _ = self.data.mark_submitted(&tracker.id, handled_days);
+        } else if self.policy.carry_over && !plan.leftover.is_zero() {
+            let _ = self.data.carry_over(tracker, plan.leftover);
+        } else {
+            let _ = self.data.remove(&tracker.id);
+        }
+
+        if !errors.is_empty() {
+            SubmissionOutcome::Failed {
+                error: errors.join("; "),
+                history_id,
+            }
+        } else if queued_errors.is_empty() {
+            SubmissionOutcome::Submitted {
+                worklogs,
+                history_id,
+            }
+        } else {
+            SubmissionOutcome::Queued {
+                worklogs,
+                queued: queued_errors.len(),
+                error: queued_errors.join("; "),
+                history_id,
+            }
+        }
+    }
+
+    /// Compares the planned worklogs with the worklogs booked in their backends on the same days.
+    ///
+    /// Fails if a backend cannot be queried. Forcing the submission skips the check, so worklogs
+    /// which cannot be submitted because the backend is not reachable are handed over to the
+    /// outbox.
+    async fn find_duplicates(
+        &self,
+        plans: &[(TrackerInformation, Plan)],
+    ) -> Result<Vec<DuplicateWorklog>, SinkError> {
+        let queries =
+            planned_ranges(plans)
+                .into_iter()
+                .map(|((account, backend), (from, to))| async move {
+                    let sink = self.accounts.sink(account.as_deref(), backend)?;
+                    let booked = sink.get_worklogs(from, to).await?;
+                    Ok::<_, SinkError>(((account, backend), booked))
+                });
+        let booked: HashMap<SinkKey, Vec<BookedWorklog>> =
+            try_join_all(queries).await?.into_iter().collect();
+
+        let mut duplicates = Vec::new();
+        for (tracker, plan) in plans {
+            for worklog in &plan.worklogs {
+                let date = worklog.start.date_naive();
+                let booked = booked.get(&sink_key(worklog)).map(Vec::as_slice);
+                let existing = booked
+                    .unwrap_or_default()
+                    .iter()
+                    .find(|booked| booked.matches(worklog));
+                if let Some(existing) = existing {
+                    duplicates.push(DuplicateWorklog {
+                        id: tracker.id.clone(),
+                        key: tracker.key.clone(),
+                        date,
+                        duration: worklog.duration,
+                        account: self.accounts.tracker_account(tracker),
+                        backend: existing.backend,
+                        worklog_id: existing.worklog_id,
+                        booked_duration: Duration::from_secs(existing.time_spent_seconds),
+                    });
+                }
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Submits the selected trackers, keeping all other trackers as they are
+    pub async fn submit(
+        &self,
+        selection: SubmitSelection,
+        options: SubmitOptions,
+    ) -> Result<SubmissionReport, SubmitError> {
+        let trackers = self.data.list_trackers();
+        let unknown = selection.trackers.iter().find(|id| {
+            !trackers
+                .iter()
+                .any(|tracker| **id == tracker.id || **id == tracker.key)
+        });
+        if let Some(id) = unknown {
+            return Err(SubmitError::UnknownTracker(id.clone()));
+        }
+
+        let before = selection.before;
+        let trackers = trackers
+            .into_iter()
+            .filter(|tracker| selection.selects(tracker))
+            .filter(|tracker| {
+                before.is_none_or(|before| {
+                    self.pending_worklogs(tracker)
+                        .iter()
+                        .any(|worklog| worklog.tracked_day() < before)
+                })
+            })
+            .collect();
+        self.submit_trackers(trackers, before, options).await
+    }
+
+    pub async fn submit_tracker_by_id(
+        &self,
+        id: &str,
+        options: SubmitOptions,
+    ) -> Result<TrackerSubmission, SubmitError> {
+        let tracker = self.data.get_tracker(id)?;
+        let mut report = self.submit_trackers(vec![tracker], None, options).await?;
+        Ok(report.trackers.remove(0))
+    }
+
+    /// Refuses to submit anything if probable duplicates are found or cannot be checked, unless
+    /// forced
+    async fn submit_trackers(
+        &self,
+        trackers: Vec<TrackerInformation>,
+        before: Option<NaiveDate>,
+        options: SubmitOptions,
+    ) -> Result<SubmissionReport, SubmitError> {
+        let (trackers, unresolved): (Vec<_>, Vec<_>) = trackers
+            .into_iter()
+            .partition(|tracker| tracker.issue_id.is_some());
+        let mut plans = Vec::new();
+        let mut unplanned = Vec::new();
+        for tracker in trackers {
+            match self.plan(&tracker, before) {
+                Ok(plan) => plans.push((tracker, plan)),
+                Err(e) => unplanned.push((tracker, e)),
+            }
+        }
+
+        let locked = self.locked_periods(&plans).await;
+        for (_, plan) in plans.iter_mut() {
+            apply_locked_periods(plan, &locked, options.move_to_open, self.timezone);
+        }
+
+        if !options.force {
+            let duplicates = self
+                .find_duplicates(&plans)
+                .await
+                .map_err(SubmitError::DuplicateCheck)?;
+            if !duplicates.is_empty() {
+                return Err(SubmitError::Duplicates(duplicates));
+            }
+        }
+
+        let submissions = plans
+            .into_iter()
+            .map(|(tracker, plan)| self.submit_tracker(tracker, plan));
+        let mut report = SubmissionReport {
+            trackers: join_all(submissions).await,
+        };
+        report
+            .trackers
+            .extend(unplanned.into_iter().map(|(tracker, e)| TrackerSubmission {
+                outcome: SubmissionOutcome::Failed {
+                    error: e.to_string(),
+                    history_id: None,
+                },
+                id: tracker.id,
+                key: tracker.key,
+            }));
+        report
+            .trackers
+            .extend(unresolved.into_iter().map(|tracker| TrackerSubmission {
+                outcome: match tracker.issue_error {
+                    Some(_) => SubmissionOutcome::Failed {
+                        error: unresolved_reason(&tracker),
+                        history_id: None,
+                    },
+                    None => SubmissionOutcome::Skipped {
+                        reason: unresolved_reason(&tracker),
+                    },
+                },
+                id: tracker.id,
+                key: tracker.key,
+            }));
+        Ok(report)
+    }
+
+    /// Deletes the worklogs and queued worklogs of a history entry and restores its tracker
+    pub async fn unsubmit(&self, history_id: &str) -> Result<TrackerInformation, UnsubmitError> {
+        self.outbox.hold_history(history_id).await;
+        let entry = match self.delete_worklogs(history_id).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.outbox.release_history(history_id);
+                return Err(e);
+            }
+        };
+        let cancelled = self.outbox.cancel_history(history_id);
+
+        self.history.remove(history_id);
+        let days: Vec<_> = entry
+            .worklogs
+            .iter()
+            .map(|submitted| &submitted.worklog)
+            .chain(cancelled.iter().map(|item| &item.worklog))
+            .map(|worklog| self.handled_time(worklog))
+            .collect();
+        Ok(self.data.restore(entry.tracker, &days))
+    }
+
+    /// Deletes the booked worklogs of a history entry, removing the deleted ones from the entry
+    /// if some of them cannot be deleted
+    async fn delete_worklogs(&self, history_id: &str) -> Result<HistoryEntry, UnsubmitError> {
+        let entry = self.history.get(history_id)?;
+        let results = join_all(entry.worklogs.iter().map(|submitted| async {
+            let result = match self.accounts.worklog_sink(&submitted.worklog) {
+                Ok(sink) => sink.delete(submitted).await,
+                Err(e) => Err(e),
+            };
+            (submitted.worklog_id, result)
+        }))
+        .await;
+        let mut deleted = Vec::new();
+        let mut error = None;
+        for (worklog_id, result) in results {
+            match result {
+                Ok(()) => deleted.push(worklog_id),
+                Err(e) => error = Some(e),
+            }
+        }
+        if let Some(error) = error {
+            self.history.remove_worklogs(history_id, &deleted);
+            return Err(UnsubmitError::Sink(error));
+        }
+        Ok(entry)
+    }
+
+    /// Combines the booked worklogs with the tracked time of local trackers and the outbox
+    pub async fn days(&self, from: NaiveDate, to: NaiveDate) -> DaysReport {
+        let booked_worklogs = self.accounts.get_worklogs(from, to).await;
+        let mut booked: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+        for booked_worklog in booked_worklogs.worklogs {
+            let worklog = booked_worklog.worklog;
+            *booked.entry(worklog.start_date).or_default() += worklog.time_spent_seconds;
+        }
+
+        let mut pending: BTreeMap<NaiveDate, Duration> = BTreeMap::new();
+        let tracked = self
+            .data
+            .list_trackers()
+            .into_iter()
+            .flat_map(|tracker| self.pending_worklogs(&tracker));
+        let queued = self.outbox.list().into_iter().map(|item| item.worklog);
+        for worklog in tracked.chain(queued) {
+            *pending.entry(worklog.start.date_naive()).or_default() += worklog.duration;
+        }
+
+        let days = from
+            .iter_days()
+            .take_while(|date| *date <= to)
+            .map(|date| {
+                let booked = Duration::from_secs(booked.get(&date).copied().unwrap_or_default());
+                let pending = pending.get(&date).copied().unwrap_or_default();
+                DaySummary {
+                    date,
+                    booked,
+                    pending,
+                    total: booked + pending,
+                }
+            })
+            .collect();
+        DaysReport {
+            days,
+            errors: booked_worklogs.errors,
+        }
+    }
+
+    /// Shows what would be sent on submission without submitting or removing anything
+    pub async fn preview(&self, options: SubmitOptions) -> Vec<TrackerPreview> {
+        let mut plans = Vec::new();
+        let mut previews = Vec::new();
+        for tracker in self.data.list_trackers() {
+            match self.plan(&tracker, None) {
+                Ok(plan) => plans.push((tracker, plan)),
+                Err(e) => previews.push(TrackerPreview {
+                    id: tracker.id,
+                    key: tracker.key,
+                    plan: PreviewPlan::Skip {
+                        reason: e.to_string(),
+                        leftover: Duration::ZERO,
+                    },
+                }),
+            }
+        }
+        let locked = self.locked_periods(&plans).await;
+        for (_, plan) in plans.iter_mut() {
+            apply_locked_periods(plan, &locked, options.move_to_open, self.timezone);
+        }
+
+        let planned = plans.into_iter().map(|(tracker, plan)| {
+            let Plan {
+                worklogs,
+                rejected,
+                leftover,
+                notes,
+                ..
+            } = plan;
+            let rejected: Vec<_> = rejected
+                .iter()
+                .map(|(worklog, reason)| format!("{}: {}", worklog.start.date_naive(), reason))
+                .collect();
+            let bodies: Result<Vec<_>, _> = worklogs
+                .into_iter()
+                .map(|worklog| self.accounts.worklog_sink(&worklog)?.request_body(worklog))
+                .collect();
+            let plan = match bodies {
+                _ if tracker.issue_id.is_none() => PreviewPlan::Skip {
+                    reason: unresolved_reason(&tracker),
+                    leftover,
+                },
+                Ok(worklogs) if worklogs.is_empty() && rejected.is_empty() => PreviewPlan::Skip {
+                    reason: self.skip_reason(),
+                    leftover,
+                },
+                Ok(worklogs) => PreviewPlan::Submit {
+                    worklogs,
+                    rejected,
+                    notes,
+                    leftover,
+                },
+                Err(e) => PreviewPlan::Skip {
+                    reason: e.to_string(),
+                    leftover,
+                },
+            };
+            TrackerPreview {
+                id: tracker.id,
+                key: tracker.key,
+                plan,
+            }
+        });
+        previews.extend(planned);
+        previews
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, TimeZone};
+    use chrono_tz::Europe::Berlin;
+    use domain::Segment;
+
+    use super::*;
+    use crate::worklog_sink::Period;
+
+    const TIMEZONE: Timezone = Timezone::Named(Berlin);
+
+    fn berlin(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Berlin
+            .with_ymd_and_hms(2024, 3, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    fn tracker(
+        segments: Vec<Segment>,
+        submitted_seconds: BTreeMap<NaiveDate, u64>,
+    ) -> TrackerInformation {
+        let duration = segments.iter().map(Segment::duration).sum();
+        TrackerInformation {
+            id: "1".to_string(),
+            key: "ABC-1".to_string(),
+            issue_id: Some("10001".to_string()),
+            issue_error: None,
+            description: None,
+            duration,
+            running: false,
+            start_time: segments[0].start,
+            first_start: segments.first().map(|segment| segment.start),
+            last_start: segments.last().map(|segment| segment.start),
+            segments,
+            submitted_seconds,
+            tempo: TempoAttributes::default(),
+            account: None,
+        }
+    }
+
+    fn pending(tracker: &TrackerInformation) -> Vec<(NaiveDate, u64)> {
+        pending_worklogs(tracker, WorklogStart::FirstStart, TIMEZONE)
+            .iter()
+            .map(|worklog| (worklog.start.date_naive(), worklog.duration.as_secs() / 60))
+            .collect()
+    }
+
+    #[test]
+    fn time_tracked_after_a_submission_is_still_pending() {
+        let segments = vec![
+            Segment {
+                start: berlin(4, 9, 0),
+                end: berlin(4, 10, 0),
+            },
+            Segment {
+                start: berlin(4, 14, 0),
+                end: berlin(4, 14, 30),
+            },
+            Segment {
+                start: berlin(5, 9, 0),
+                end: berlin(5, 9, 45),
+            },
+        ];
+        let submitted = BTreeMap::from([(date(4), minutes(60).as_secs())]);
+        assert_eq!(
+            pending(&tracker(segments.clone(), BTreeMap::new())),
+            vec![(date(4), 90), (date(5), 45)]
+        );
+        assert_eq!(
+            pending(&tracker(segments, submitted)),
+            vec![(date(4), 30), (date(5), 45)]
+        );
+    }
+
+    #[test]
+    fn fully_submitted_days_are_not_pending() {
+        let segments = vec![Segment {
+            start: berlin(4, 9, 0),
+            end: berlin(4, 10, 0),
+        }];
+        let submitted = BTreeMap::from([(date(4), minutes(60).as_secs())]);
+        assert!(pending(&tracker(segments, submitted)).is_empty());
+    }
+
+    fn planned(days: &[u32]) -> Plan {
+        let segments = days
+            .iter()
+            .map(|day| Segment {
+                start: berlin(*day, 9, 0),
+                end: berlin(*day, 10, 0),
+            })
+            .collect();
+        Plan {
+            worklogs: pending_worklogs(
+                &tracker(segments, BTreeMap::new()),
+                WorklogStart::FirstStart,
+                TIMEZONE,
+            ),
+            rejected: Vec::new(),
+            leftover: Duration::ZERO,
+            kept: false,
+            notes: Vec::new(),
+        }
+    }
+
+    fn locked(result: Result<Vec<LockedPeriod>, SinkError>) -> LockedPeriods {
+        HashMap::from([((None, Backend::default()), result)])
+    }
+
+    fn approved(from: u32, to: u32) -> LockedPeriod {
+        LockedPeriod {
+            period: Period {
+                from: date(from),
+                to: date(to),
+            },
+            status: "approved".to_string(),
+        }
+    }
+
+    fn worklog_days(worklogs: &[Worklog]) -> Vec<NaiveDate> {
+        worklogs
+            .iter()
+            .map(|worklog| worklog.start.date_naive())
+            .collect()
+    }
+
+    #[test]
+    fn worklogs_in_locked_periods_are_rejected() {
+        let mut plan = planned(&[4, 5]);
+        apply_locked_periods(
+            &mut plan,
+            &locked(Ok(vec![approved(1, 4)])),
+            false,
+            TIMEZONE,
+        );
+        assert_eq!(worklog_days(&plan.worklogs), vec![date(5)]);
+        assert_eq!(plan.rejected.len(), 1);
+        assert_eq!(
+            plan.rejected[0].1,
+            "period 2024-03-01 to 2024-03-04 is approved"
+        );
+    }
+
+    #[test]
+    fn worklogs_in_locked_periods_are_moved_to_the_first_open_day() {
+        let mut plan = planned(&[4]);
+        let periods = vec![approved(1, 4), approved(5, 6)];
+        apply_locked_periods(&mut plan, &locked(Ok(periods)), true, TIMEZONE);
+        assert_eq!(worklog_days(&plan.worklogs), vec![date(7)]);
+        assert_eq!(plan.worklogs[0].tracked_day(), date(4));
+        assert!(plan.rejected.is_empty());
+    }
+
+    #[test]
+    fn worklogs_with_unknown_locked_periods_are_kept_with_a_note() {
+        let mut plan = planned(&[4, 5]);
+        let unavailable = SinkError::Unavailable("default".to_string());
+        apply_locked_periods(&mut plan, &locked(Err(unavailable)), false, TIMEZONE);
+        assert_eq!(worklog_days(&plan.worklogs), vec![date(4), date(5)]);
+        assert!(plan.rejected.is_empty());
+        assert_eq!(plan.notes.len(), 2);
+    }
+}
@@ -1,18 +1,26 @@
-use std::time::Duration;
-
+use chrono::{Local, NaiveDate, NaiveTime};
 use futures::future::try_join_all;
+use futures::FutureExt;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
-use serde::Serialize;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 
-use crate::config::AppConfig;
-use domain::TrackerInformation;
+use crate::accounts::AccountId;
+use crate::config::{AccountConfig, AppConfig, Backend, HttpConfig};
+use crate::http::{self, HttpClient};
+use crate::worklog::Worklog;
+use crate::worklog_sink::{
+    BookedWorklog, LockedPeriod, Period, SinkError, SinkFuture, SubmittedWorklog, WorklogSink,
+};
 
 pub struct TempoApi {
-    client: reqwest::Client,
-    jira_account_id: String,
+    client: HttpClient,
+    base_uri: String,
+    jira_account_id: AccountId,
+    account_attribute: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct SubmitWorklogBody {
     #[serde(rename = "issueId")]
     issue_id: String,
@@ -25,72 +33,281 @@ struct SubmitWorklogBody {
     description: Option<String>,
     #[serde(rename = "authorAccountId")]
     author_account_id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attributes: Vec<WorkAttributeValue>,
+    #[serde(
+        default,
+        rename = "billableSeconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    billable_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkAttributeValue {
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorklogResponse {
+    #[serde(rename = "tempoWorklogId")]
+    tempo_worklog_id: u64,
 }
 
-impl<ID> From<(TrackerInformation, ID)> for SubmitWorklogBody
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TempoWorklog {
+    tempo_worklog_id: u64,
+    issue: TempoIssue,
+    time_spent_seconds: u64,
+    start_date: NaiveDate,
+    start_time: NaiveTime,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TempoIssue {
+    id: u64,
+}
+
+impl From<TempoWorklog> for BookedWorklog {
+    fn from(worklog: TempoWorklog) -> Self {
+        Self {
+            backend: Backend::Tempo,
+            worklog_id: worklog.tempo_worklog_id,
+            issue_id: worklog.issue.id.to_string(),
+            time_spent_seconds: worklog.time_spent_seconds,
+            start_date: worklog.start_date,
+            start_time: worklog.start_time,
+            description: worklog.description,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PeriodsResponse {
+    periods: Vec<Period>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApprovalResponse {
+    status: ApprovalStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApprovalStatus {
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorklogsPage {
+    results: Vec<TempoWorklog>,
+    metadata: PageMetadata,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageMetadata {
+    next: Option<String>,
+}
+
+impl<ID> From<(Worklog, ID)> for SubmitWorklogBody
 where
     ID: Into<String>,
 {
-    fn from((tracker, author_account_id): (TrackerInformation, ID)) -> Self {
+    fn from((worklog, author_account_id): (Worklog, ID)) -> Self {
+        let time_spent_seconds = worklog.duration.as_secs();
         Self {
-            issue_id: tracker.id,
-            time_spent_seconds: tracker.duration.as_secs(),
-            start_date: tracker.start_time.format("%Y-%m-%d").to_string(),
-            start_time: tracker.start_time.format("%H:%M:%S").to_string(),
-            description: tracker.description,
+            issue_id: worklog.issue_id,
+            time_spent_seconds,
+            start_date: worklog.start.format("%Y-%m-%d").to_string(),
+            start_time: worklog.start.format("%H:%M:%S").to_string(),
+            description: worklog.description,
             author_account_id: author_account_id.into(),
+            attributes: worklog
+                .tempo
+                .attributes
+                .into_iter()
+                .map(|(key, value)| WorkAttributeValue { key, value })
+                .collect(),
+            billable_seconds: worklog.tempo.billable.map(|billable| {
+                if billable {
+                    time_spent_seconds
+                } else {
+                    0
+                }
+            }),
         }
     }
 }
 
 impl TempoApi {
-    fn new<ID: Into<String>>(tempo_api_token: &str, jira_account_id: ID) -> Self {
-        let mut authorization_value: HeaderValue =
-            format!("Bearer {}", tempo_api_token).parse().unwrap();
-        authorization_value.set_sensitive(true);
-
+    /// Without token, requests are rejected by Tempo, which is only the case if no worklogs are
+    /// booked in Tempo
+    fn new(
+        tempo_url: &str,
+        tempo_api_token: Option<&str>,
+        jira_account_id: AccountId,
+        account_attribute: &str,
+        http: &HttpConfig,
+    ) -> Self {
         let mut headers = HeaderMap::new();
-        headers.insert(AUTHORIZATION, authorization_value);
+        if let Some(tempo_api_token) = tempo_api_token {
+            let mut authorization_value: HeaderValue =
+                format!("Bearer {}", tempo_api_token).parse().unwrap();
+            authorization_value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, authorization_value);
+        }
 
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .unwrap();
+        let client = HttpClient::new(headers, http);
 
         Self {
             client,
-            jira_account_id: jira_account_id.into(),
+            base_uri: format!("{}/4", tempo_url.trim_end_matches('/')),
+            jira_account_id,
+            account_attribute: account_attribute.to_string(),
         }
     }
 
-    pub async fn submit(&self, tracker: TrackerInformation) -> Result<(), reqwest::Error> {
-        let request: SubmitWorklogBody = (tracker, &self.jira_account_id).into();
+    fn payload(&self, mut worklog: Worklog) -> Result<SubmitWorklogBody, SinkError> {
+        if let Some(account) = worklog.tempo.account.take() {
+            worklog
+                .tempo
+                .attributes
+                .insert(self.account_attribute.clone(), account);
+        }
+        Ok((worklog, self.jira_account_id.get()?).into())
+    }
+
+    async fn submit_worklog(&self, worklog: Worklog) -> Result<SubmittedWorklog, SinkError> {
+        let payload = self.payload(worklog.clone())?;
         let builder = self
             .client
-            .post("https://api.tempo.io/4/worklogs")
-            .json(&request);
-        builder.send().await?.error_for_status()?;
-        Ok(())
+            .post(format!("{}/worklogs", self.base_uri))
+            .json(&payload);
+        let response = self.client.send(builder).await?;
+        let WorklogResponse { tempo_worklog_id } = http::processed_json(response).await?;
+        Ok(SubmittedWorklog {
+            worklog_id: tempo_worklog_id,
+            submitted_at: Local::now(),
+            worklog,
+            payload: serde_json::to_value(payload).unwrap(),
+        })
+    }
+
+    async fn fetch_worklogs(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<BookedWorklog>, SinkError> {
+        let url = format!(
+            "{}/worklogs/user/{}",
+            self.base_uri,
+            self.jira_account_id.get()?
+        );
+        let query = [
+            ("from", from.to_string()),
+            ("to", to.to_string()),
+            ("limit", "1000".to_string()),
+        ];
+        let mut request = self.client.get(url).query(&query);
+        let mut worklogs = Vec::new();
+        loop {
+            let response = self.client.send(request).await?;
+            let page: WorklogsPage = response.json().await?;
+            worklogs.extend(page.results.into_iter().map(BookedWorklog::from));
+            match page.metadata.next {
+                Some(next) => request = self.client.get(next),
+                None => return Ok(worklogs),
+            }
+        }
+    }
+
+    async fn get_approval_status(&self, period: &Period) -> Result<String, SinkError> {
+        let url = format!(
+            "{}/timesheet-approvals/user/{}",
+            self.base_uri,
+            self.jira_account_id.get()?
+        );
+        let query = [("from", period.from), ("to", period.to)];
+        let request = self.client.get(url).query(&query);
+        let response = self.client.send(request).await?;
+        let approval: ApprovalResponse = response.json().await?;
+        Ok(approval.status.key)
     }
 
-    pub async fn submit_all(
+    async fn fetch_locked_periods(
         &self,
-        trackers: Vec<TrackerInformation>,
-    ) -> Result<(), reqwest::Error> {
-        let results: Vec<_> = trackers
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<LockedPeriod>, SinkError> {
+        let query = [("from", from), ("to", to)];
+        let request = self
+            .client
+            .get(format!("{}/periods", self.base_uri))
+            .query(&query);
+        let response = self.client.send(request).await?;
+        let PeriodsResponse { periods } = response.json().await?;
+
+        let statuses = try_join_all(
+            periods
+                .iter()
+                .map(|period| self.get_approval_status(period)),
+        )
+        .await?;
+        Ok(periods
             .into_iter()
-            .filter(|tracker| tracker.duration >= Duration::from_secs(60))
-            .map(|tracker| self.submit(tracker))
-            .collect();
-        try_join_all(results).await.map(|_| ())
+            .zip(statuses)
+            .filter(|(_, status)| status != "OPEN")
+            .map(|(period, status)| LockedPeriod { period, status })
+            .collect())
+    }
+
+    async fn delete_worklog(&self, tempo_worklog_id: u64) -> Result<(), SinkError> {
+        let url = format!("{}/worklogs/{}", self.base_uri, tempo_worklog_id);
+        match self.client.send(self.client.delete(url)).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
     }
 }
 
-impl<ID> From<(&AppConfig, ID)> for TempoApi
-where
-    ID: Into<String>,
-{
-    fn from((config, jira_account_id): (&AppConfig, ID)) -> Self {
-        TempoApi::new(&config.tempo_api_token, jira_account_id.into())
+impl WorklogSink for TempoApi {
+    fn request_body(&self, worklog: Worklog) -> Result<serde_json::Value, SinkError> {
+        Ok(serde_json::to_value(self.payload(worklog)?).unwrap())
+    }
+
+    fn submit(&self, worklog: Worklog) -> SinkFuture<'_, SubmittedWorklog> {
+        self.submit_worklog(worklog).boxed()
+    }
+
+    fn get_worklogs(&self, from: NaiveDate, to: NaiveDate) -> SinkFuture<'_, Vec<BookedWorklog>> {
+        self.fetch_worklogs(from, to).boxed()
+    }
+
+    /// Periods whose timesheet approval is not open are locked
+    fn get_locked_periods(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> SinkFuture<'_, Vec<LockedPeriod>> {
+        self.fetch_locked_periods(from, to).boxed()
+    }
+
+    fn delete<'a>(&'a self, submitted: &'a SubmittedWorklog) -> SinkFuture<'a, ()> {
+        self.delete_worklog(submitted.worklog_id).boxed()
+    }
+}
+
+impl From<(&AppConfig, &AccountConfig, AccountId)> for TempoApi {
+    fn from((config, account, jira_account_id): (&AppConfig, &AccountConfig, AccountId)) -> Self {
+        TempoApi::new(
+            &account.tempo_url,
+            account.tempo_api_token.as_deref(),
+            jira_account_id,
+            &config.tempo_account_attribute,
+            &config.http,
+        )
     }
 }
@@ -1,50 +1,95 @@
-use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
 
-use axum::extract::{Path, State};
+use axum::body::Bytes;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::header::ALLOW;
 use axum::http::StatusCode;
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{Json, Router};
+use chrono::NaiveDate;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::accounts::{AccountWorklogs, Accounts};
 use crate::app_data::{AppData, TrackerError};
-use crate::config::LogError;
-use crate::jira_api::JiraApi;
-use crate::tempo_api::TempoApi;
+use crate::config::Timezone;
+use crate::error::{ApiError, ErrorKey};
+use crate::history::{History, HistoryEntry};
+use crate::outbox::{Outbox, OutboxItem};
+use crate::schedule::{ScheduleStatus, Scheduler};
+use crate::submission::{
+    DaysReport, SubmissionReport, SubmitOptions, SubmitSelection, Submitter, TrackerPreview,
+    TrackerSubmission,
+};
 use crate::AppState;
-use domain::TrackerInformation;
+use domain::{Segment, TempoAttributes, TrackerInformation};
 
 async fn list(State(state): State<Arc<AppData>>) -> Json<Vec<TrackerInformation>> {
     Json(state.list_trackers())
 }
 
 async fn get_tracker(
-    Path(key): Path<String>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppData>>,
+) -> Result<Json<TrackerInformation>, ApiError> {
+    state.get_tracker(&id).map(Json).for_key(&id)
+}
+
+async fn segments(
+    Path(id): Path<String>,
     State(state): State<Arc<AppData>>,
-) -> Result<Json<TrackerInformation>, TrackerError> {
-    state.get_tracker(&key).map(Json)
+) -> Result<Json<Vec<Segment>>, ApiError> {
+    state
+        .get_tracker(&id)
+        .map(|tracker| Json(tracker.segments))
+        .for_key(&id)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTrackerBody {
+    description: Option<String>,
+    /// Chosen by the project of the key if not set
+    account: Option<String>,
 }
 
 async fn create(
     Path(key): Path<String>,
-    State(jira): State<Arc<JiraApi>>,
+    State(accounts): State<Arc<Accounts>>,
     State(state): State<Arc<AppData>>,
-) -> Result<Json<TrackerInformation>, TrackerError> {
-    let issue = jira
-        .get_issue_info(&key)
-        .await
-        .map_err(|_| TrackerError::NotFoundError)?;
-    state.create_tracker(&key, &issue.id)?;
-    let tracker = state.start(&key)?;
+    body: Bytes,
+) -> Result<Json<TrackerInformation>, ApiError> {
+    // an invalid body must not fall back to a tracker without description and account
+    let (description, account) = match optional_json::<CreateTrackerBody>(&body)? {
+        Some(body) => (body.description, body.account),
+        None => (None, None),
+    };
+    let account_name = accounts.account_name(&key, account.as_deref());
+    let jira = &accounts.get(&account_name).for_key(&account_name)?.jira_api;
+    // the issue id is resolved in the background if Jira is not reachable
+    let issue_id = match jira.get_issue_info(&key).await {
+        Ok(issue) => Some(issue.id),
+        Err(e) if e.is_transient() && e.status() != Some(StatusCode::TOO_MANY_REQUESTS) => {
+            tracing::info!("creating tracker for unresolved issue {}: {}", key, e);
+            None
+        }
+        Err(e) => return Err(TrackerError::from(e)).for_key(&key),
+    };
+    let tracker = state
+        .create_tracker(&key, issue_id, description, account)
+        .for_key(&key)?;
+    let tracker = state.start(&tracker.id).for_key(&tracker.id)?;
     Ok(Json(tracker))
 }
 
 async fn start(
-    Path(key): Path<String>,
+    Path(id): Path<String>,
     State(state): State<Arc<AppData>>,
-) -> Result<Json<TrackerInformation>, TrackerError> {
-    state.start(&key).map(Json)
+) -> Result<Json<TrackerInformation>, ApiError> {
+    state.start(&id).map(Json).for_key(&id)
 }
 
 #[derive(Debug, Deserialize)]
@@ -76,39 +121,53 @@ enum AdjustTrackerBody {
         #[serde(alias = "to")]
         using: Option<String>,
     },
+    SetTempoAttributes {
+        tempo: TempoAttributes,
+    },
 }
 
 async fn adjust(
-    Path(key): Path<String>,
+    Path(id): Path<String>,
     State(state): State<Arc<AppData>>,
-    Json(body): Json<AdjustTrackerBody>,
-) -> Result<Json<TrackerInformation>, TrackerError> {
+    body: Result<Json<AdjustTrackerBody>, JsonRejection>,
+) -> Result<Json<TrackerInformation>, ApiError> {
+    let Json(body) = body?;
     let tracker = match body {
         AdjustTrackerBody::SetDescription { description } => {
-            state.set_description(&key, description)?
+            state.set_description(&id, description).for_key(&id)?
         }
         AdjustTrackerBody::PositiveDuration { duration, using } => {
-            if let Some(other_key) = using {
-                state.adjust_negative_duration(&other_key, duration)?;
+            if let Some(other_id) = using {
+                state
+                    .adjust_negative_duration(&other_id, duration)
+                    .for_key(&other_id)?;
             }
-            state.adjust_positive_duration(&key, duration)?
+            state.adjust_positive_duration(&id, duration).for_key(&id)?
         }
         AdjustTrackerBody::NegativeDuration { duration, using } => {
-            let tracker = state.adjust_negative_duration(&key, duration)?;
-            if let Some(other_key) = using {
-                state.adjust_positive_duration(&other_key, duration)?;
+            let tracker = state.adjust_negative_duration(&id, duration).for_key(&id)?;
+            if let Some(other_id) = using {
+                state
+                    .adjust_positive_duration(&other_id, duration)
+                    .for_key(&other_id)?;
             }
             tracker
         }
+        AdjustTrackerBody::SetTempoAttributes { tempo } => {
+            state.set_tempo_attributes(&id, tempo).for_key(&id)?
+        }
     };
     Ok(Json(tracker))
 }
 
 async fn delete(
-    Path(key): Path<String>,
+    Path(id): Path<String>,
     State(state): State<Arc<AppData>>,
-) -> Result<StatusCode, TrackerError> {
-    state.remove(&key).map(|_| StatusCode::NO_CONTENT)
+) -> Result<StatusCode, ApiError> {
+    state
+        .remove(&id)
+        .map(|_| StatusCode::NO_CONTENT)
+        .for_key(&id)
 }
 
 async fn clear(State(state): State<Arc<AppData>>) -> StatusCode {
@@ -116,10 +175,8 @@ async fn clear(State(state): State<Arc<AppData>>) -> StatusCode {
     StatusCode::NO_CONTENT
 }
 
-async fn current(
-    State(state): State<Arc<AppData>>,
-) -> Result<Json<TrackerInformation>, TrackerError> {
-    state.current().map(Json)
+async fn current(State(state): State<Arc<AppData>>) -> Result<Json<TrackerInformation>, ApiError> {
+    Ok(Json(state.current()?))
 }
 
 async fn pause(State(state): State<Arc<AppData>>) {
@@ -138,31 +195,204 @@ async fn sum(State(state): State<Arc<AppData>>) -> Json<SumResponse> {
     })
 }
 
+/// Parses a JSON body regardless of its content type, an empty body is no body
+fn optional_json<T: DeserializeOwned>(body: &[u8]) -> Result<Option<T>, ApiError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    serde_json::from_slice(body).map(Some).map_err(|e| {
+        let status = if e.is_data() {
+            StatusCode::UNPROCESSABLE_ENTITY
+        } else {
+            StatusCode::BAD_REQUEST
+        };
+        ApiError::new(status, "invalid_body", e.to_string())
+    })
+}
+
 async fn submit(
-    State(state): State<Arc<AppData>>,
-    State(api): State<Arc<TempoApi>>,
-) -> Result<(), LogError> {
-    api.submit_all(state.list_trackers()).await?;
-    state.remove_all();
-    Ok(())
+    options: Result<Query<SubmitOptions>, QueryRejection>,
+    State(submitter): State<Arc<Submitter>>,
+    body: Bytes,
+) -> Result<Json<SubmissionReport>, ApiError> {
+    let Query(options) = options?;
+    // only a missing selection submits everything, an invalid one is rejected
+    let selection: SubmitSelection = optional_json(&body)?.unwrap_or_default();
+    Ok(Json(submitter.submit(selection, options).await?))
+}
+
+async fn submit_tracker(
+    Path(id): Path<String>,
+    options: Result<Query<SubmitOptions>, QueryRejection>,
+    State(submitter): State<Arc<Submitter>>,
+) -> Result<Json<TrackerSubmission>, ApiError> {
+    let Query(options) = options?;
+    submitter
+        .submit_tracker_by_id(&id, options)
+        .await
+        .map(Json)
+        .for_key(&id)
+}
+
+async fn list_outbox(State(outbox): State<Arc<Outbox>>) -> Json<Vec<OutboxItem>> {
+    Json(outbox.list())
+}
+
+async fn get_outbox_item(
+    Path(id): Path<String>,
+    State(outbox): State<Arc<Outbox>>,
+) -> Result<Json<OutboxItem>, ApiError> {
+    outbox.get(&id).map(Json).for_key(&id)
+}
+
+async fn retry_outbox(State(outbox): State<Arc<Outbox>>) -> StatusCode {
+    outbox.retry_all();
+    StatusCode::ACCEPTED
+}
+
+async fn retry_outbox_item(
+    Path(id): Path<String>,
+    State(outbox): State<Arc<Outbox>>,
+) -> Result<(StatusCode, Json<OutboxItem>), ApiError> {
+    outbox
+        .retry(&id)
+        .map(|item| (StatusCode::ACCEPTED, Json(item)))
+        .for_key(&id)
+}
+
+async fn cancel_outbox_item(
+    Path(id): Path<String>,
+    State(outbox): State<Arc<Outbox>>,
+) -> Result<Json<OutboxItem>, ApiError> {
+    outbox.cancel(&id).map(Json).for_key(&id)
+}
+
+async fn preview_submit(
+    options: Result<Query<SubmitOptions>, QueryRejection>,
+    State(submitter): State<Arc<Submitter>>,
+) -> Result<Json<Vec<TrackerPreview>>, ApiError> {
+    let Query(options) = options?;
+    Ok(Json(submitter.preview(options).await))
+}
+
+#[derive(Debug, Deserialize)]
+struct DateRangeQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+impl DateRangeQuery {
+    /// Defaults to today in the configured time zone, or to a single day if only the start is
+    /// given
+    fn days(&self, timezone: Timezone) -> (NaiveDate, NaiveDate) {
+        let from = self.from.unwrap_or_else(|| timezone.today());
+        (from, self.to.unwrap_or(from))
+    }
+}
+
+async fn list_history(
+    range: Result<Query<DateRangeQuery>, QueryRejection>,
+    State(history): State<Arc<History>>,
+) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let Query(range) = range?;
+    Ok(Json(history.list(range.from, range.to)))
+}
+
+async fn get_history_entry(
+    Path(id): Path<String>,
+    State(history): State<Arc<History>>,
+) -> Result<Json<HistoryEntry>, ApiError> {
+    history.get(&id).map(Json).for_key(&id)
+}
+
+async fn unsubmit(
+    Path(id): Path<String>,
+    State(submitter): State<Arc<Submitter>>,
+) -> Result<Json<TrackerInformation>, ApiError> {
+    submitter.unsubmit(&id).await.map(Json).for_key(&id)
+}
+
+async fn list_worklogs(
+    range: Result<Query<DateRangeQuery>, QueryRejection>,
+    State(accounts): State<Arc<Accounts>>,
+    State(timezone): State<Timezone>,
+) -> Result<Json<AccountWorklogs>, ApiError> {
+    let (from, to) = range?.days(timezone);
+    Ok(Json(accounts.get_worklogs(from, to).await))
+}
+
+async fn days(
+    range: Result<Query<DateRangeQuery>, QueryRejection>,
+    State(submitter): State<Arc<Submitter>>,
+    State(timezone): State<Timezone>,
+) -> Result<Json<DaysReport>, ApiError> {
+    let (from, to) = range?.days(timezone);
+    Ok(Json(submitter.days(from, to).await))
+}
+
+async fn schedule(State(scheduler): State<Arc<Scheduler>>) -> Json<ScheduleStatus> {
+    Json(scheduler.status())
+}
+
+async fn fallback() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "no_route", "no such endpoint")
+}
+
+/// Gives the empty responses of routes which do not support the method an error body
+async fn method_not_allowed(response: Response) -> Response {
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+    let allow = response.headers().get(ALLOW).cloned();
+    let mut response = ApiError::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+        "the endpoint does not support this method",
+    )
+    .into_response();
+    if let Some(allow) = allow {
+        response.headers_mut().insert(ALLOW, allow);
+    }
+    response
 }
 
 pub fn router() -> Router<AppState> {
     let trackers_routes = Router::new()
         .route("/", get(list).delete(clear))
         .route(
-            "/:key",
+            "/:id",
             get(get_tracker).post(create).put(adjust).delete(delete),
         )
-        .route("/:key/start", post(start));
+        .route("/:id/start", post(start))
+        .route("/:id/submit", post(submit_tracker))
+        .route("/:id/segments", get(segments));
 
     let tracker_routes = Router::new()
         .route("/", get(current))
         .route("/pause", post(pause));
 
+    let outbox_routes = Router::new()
+        .route("/", get(list_outbox))
+        .route("/retry", post(retry_outbox))
+        .route("/:id", get(get_outbox_item).delete(cancel_outbox_item))
+        .route("/:id/retry", post(retry_outbox_item));
+
+    let history_routes = Router::new()
+        .route("/", get(list_history))
+        .route("/:id", get(get_history_entry))
+        .route("/:id/unsubmit", post(unsubmit));
+
     Router::new()
         .nest("/trackers", trackers_routes)
         .nest("/tracker", tracker_routes)
+        .nest("/outbox", outbox_routes)
+        .nest("/history", history_routes)
         .route("/sum", get(sum))
+        .route("/worklogs", get(list_worklogs))
+        .route("/days", get(days))
         .route("/submit", post(submit))
+        .route("/submit/preview", get(preview_submit))
+        .route("/submit/schedule", get(schedule))
+        .fallback(fallback)
+        .layer(middleware::map_response(method_not_allowed))
 }
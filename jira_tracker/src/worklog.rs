@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use domain::{Segment, TempoAttributes, TrackerInformation};
+
+use crate::config::{Backend, Timezone, WorklogStart};
+
+/// The part of a tracker which is booked on a single calendar day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Worklog {
+    pub issue_id: String,
+    /// With the offset of the configured time zone, so its date is the day it is booked on
+    pub start: DateTime<FixedOffset>,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    /// Time tracked for the worklog, before the submit policy was applied
+    #[serde(default, with = "humantime_serde")]
+    pub tracked: Duration,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tempo: TempoAttributes,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// The day the time was tracked on, if the worklog was moved out of a locked period
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_from: Option<NaiveDate>,
+}
+
+impl Worklog {
+    pub fn tracked_day(&self) -> NaiveDate {
+        self.moved_from.unwrap_or(self.start.date_naive())
+    }
+
+    /// The day and duration the worklog accounts for in its tracker
+    pub fn tracked_time(&self) -> (NaiveDate, Duration) {
+        (self.tracked_day(), self.tracked)
+    }
+
+    /// Moves the worklog to another day, keeping its start time
+    pub fn move_to(&mut self, day: NaiveDate, timezone: Timezone) {
+        if let Some(start) = timezone.at(day.and_time(self.start.time())) {
+            self.moved_from = Some(self.tracked_day());
+            self.start = start;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Day {
+    starts: Vec<DateTime<FixedOffset>>,
+    seconds: u64,
+}
+
+fn next_midnight(time: DateTime<FixedOffset>, timezone: Timezone) -> Option<DateTime<FixedOffset>> {
+    let midnight = time
+        .date_naive()
+        .checked_add_days(Days::new(1))?
+        .and_hms_opt(0, 0, 0)?;
+    timezone.at(midnight)
+}
+
+/// A part of a segment which lies on a single day
+struct Part {
+    start: DateTime<FixedOffset>,
+    seconds: u64,
+}
+
+/// Splits a segment at the midnights of the time zone
+fn split_segment(segment: &Segment, timezone: Timezone) -> Vec<Part> {
+    let end = timezone.convert(segment.end);
+    let mut parts = Vec::new();
+    let mut start = timezone.convert(segment.start);
+    while let Some(midnight) = next_midnight(start, timezone).filter(|m| *m < end) {
+        parts.push(Part {
+            start,
+            seconds: (midnight - start).num_seconds().max(0) as u64,
+        });
+        start = midnight;
+    }
+    parts.push(Part {
+        start,
+        seconds: (end - start).num_seconds().max(0) as u64,
+    });
+    parts
+}
+
+/// Creates one worklog per calendar day the tracker was running on.
+///
+/// Manual adjustments are not bound to a day: added time is booked on the day of the worklog
+/// start, removed time is taken from the latest days first.
+pub fn split_by_day(
+    tracker: &TrackerInformation,
+    worklog_start: WorklogStart,
+    timezone: Timezone,
+) -> Vec<Worklog> {
+    let start = timezone.convert(worklog_start.start_time(tracker));
+
+    let mut days: BTreeMap<NaiveDate, Day> = BTreeMap::new();
+    let parts = tracker
+        .segments
+        .iter()
+        .flat_map(|segment| split_segment(segment, timezone));
+    for part in parts {
+        let day = days.entry(part.start.date_naive()).or_default();
+        day.starts.push(part.start);
+        day.seconds += part.seconds;
+    }
+
+    let total = tracker.duration.as_secs();
+    let segments_total: u64 = days.values().map(|day| day.seconds).sum();
+    if total > segments_total {
+        days.entry(start.date_naive()).or_default().seconds += total - segments_total;
+    } else {
+        let mut deficit = segments_total - total;
+        for day in days.values_mut().rev() {
+            let taken = deficit.min(day.seconds);
+            day.seconds -= taken;
+            deficit -= taken;
+        }
+    }
+
+    days.into_iter()
+        .filter(|(_, day)| day.seconds > 0)
+        .map(|(date, day)| {
+            let day_start = if date == start.date_naive() {
+                start
+            } else {
+                match worklog_start {
+                    WorklogStart::LastStart => day.starts.last(),
+                    WorklogStart::FirstStart | WorklogStart::Creation => day.starts.first(),
+                }
+                .copied()
+                .unwrap_or(start)
+            };
+            Worklog {
+                issue_id: tracker.issue_id.clone().unwrap_or_default(),
+                start: day_start,
+                duration: Duration::from_secs(day.seconds),
+                tracked: Duration::from_secs(day.seconds),
+                description: tracker.description.clone(),
+                tempo: tracker.tempo.clone(),
+                backend: Backend::default(),
+                account: tracker.account.clone(),
+                moved_from: None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    const TIMEZONE: Timezone = Timezone::Named(Berlin);
+
+    fn berlin(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Berlin
+            .with_ymd_and_hms(2024, 3, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn segment(start: DateTime<Local>, end: DateTime<Local>) -> Segment {
+        Segment { start, end }
+    }
+
+    fn tracker(segments: Vec<Segment>, duration: Duration) -> TrackerInformation {
+        TrackerInformation {
+            id: "1".to_string(),
+            key: "ABC-1".to_string(),
+            issue_id: Some("10001".to_string()),
+            issue_error: None,
+            description: None,
+            duration,
+            running: false,
+            start_time: segments[0].start,
+            first_start: segments.first().map(|segment| segment.start),
+            last_start: segments.last().map(|segment| segment.start),
+            segments,
+            submitted_seconds: Default::default(),
+            tempo: TempoAttributes::default(),
+            account: None,
+        }
+    }
+
+    fn days(worklogs: &[Worklog]) -> Vec<(NaiveDate, u64)> {
+        worklogs
+            .iter()
+            .map(|worklog| (worklog.start.date_naive(), worklog.duration.as_secs() / 60))
+            .collect()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn splits_segments_at_midnight() {
+        let tracker = tracker(
+            vec![segment(berlin(4, 23, 0), berlin(5, 1, 30))],
+            minutes(150),
+        );
+        let worklogs = split_by_day(&tracker, WorklogStart::FirstStart, TIMEZONE);
+        assert_eq!(days(&worklogs), vec![(date(4), 60), (date(5), 90)]);
+        assert_eq!(worklogs[1].start, TIMEZONE.convert(berlin(5, 0, 0)));
+    }
+
+    #[test]
+    fn splits_in_the_configured_time_zone() {
+        // 22:30 to 23:30 UTC crosses midnight in Berlin
+        let tracker = tracker(
+            vec![segment(berlin(4, 23, 30), berlin(5, 0, 30))],
+            minutes(60),
+        );
+        let utc = Timezone::Named(chrono_tz::UTC);
+        assert_eq!(
+            days(&split_by_day(&tracker, WorklogStart::FirstStart, utc)),
+            vec![(date(4), 60)]
+        );
+        assert_eq!(
+            days(&split_by_day(&tracker, WorklogStart::FirstStart, TIMEZONE)),
+            vec![(date(4), 30), (date(5), 30)]
+        );
+    }
+
+    #[test]
+    fn splits_across_daylight_saving_time_change() {
+        // 2024-03-31 has only 23 hours in Berlin
+        let tracker = tracker(
+            vec![segment(berlin(30, 23, 0), berlin(31, 23, 30))],
+            minutes(23 * 60 + 30),
+        );
+        let worklogs = split_by_day(&tracker, WorklogStart::FirstStart, TIMEZONE);
+        assert_eq!(
+            days(&worklogs),
+            vec![(date(30), 60), (date(31), 22 * 60 + 30)]
+        );
+    }
+
+    #[test]
+    fn adjustments_are_applied_to_start_day_and_latest_days() {
+        let segments = || vec![segment(berlin(4, 22, 0), berlin(5, 2, 0))];
+        let added = split_by_day(
+            &tracker(segments(), minutes(300)),
+            WorklogStart::FirstStart,
+            TIMEZONE,
+        );
+        assert_eq!(days(&added), vec![(date(4), 180), (date(5), 120)]);
+        let removed = split_by_day(
+            &tracker(segments(), minutes(90)),
+            WorklogStart::FirstStart,
+            TIMEZONE,
+        );
+        assert_eq!(days(&removed), vec![(date(4), 90)]);
+    }
+}
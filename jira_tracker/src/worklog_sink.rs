@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use futures::future::{try_join_all, BoxFuture};
+use futures::FutureExt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AppConfig, Backend};
+use crate::error::{ApiError, UpstreamFailure, UpstreamKind};
+use crate::http::HttpError;
+use crate::jira_api::JiraWorklogs;
+use crate::tempo_api::TempoApi;
+use crate::worklog::Worklog;
+
+pub type SinkFuture<'a, T> = BoxFuture<'a, Result<T, SinkError>>;
+
+/// A service worklogs are booked in
+pub trait WorklogSink: Send + Sync {
+    /// The payload which is sent for the worklog
+    fn request_body(&self, worklog: Worklog) -> Result<serde_json::Value, SinkError>;
+
+    fn submit(&self, worklog: Worklog) -> SinkFuture<'_, SubmittedWorklog>;
+
+    /// Fetches the worklogs of the user which start within the given days
+    fn get_worklogs(&self, from: NaiveDate, to: NaiveDate) -> SinkFuture<'_, Vec<BookedWorklog>>;
+
+    /// Fetches the periods overlapping the given days which do not accept new worklogs
+    fn get_locked_periods(
+        &self,
+        _from: NaiveDate,
+        _to: NaiveDate,
+    ) -> SinkFuture<'_, Vec<LockedPeriod>> {
+        async { Ok(Vec::new()) }.boxed()
+    }
+
+    /// Deleting a worklog which does not exist anymore is considered successful
+    fn delete<'a>(&'a self, submitted: &'a SubmittedWorklog) -> SinkFuture<'a, ()>;
+}
+
+#[derive(Debug)]
+pub enum SinkError {
+    Http(HttpError),
+    /// The account of a worklog is not configured (anymore)
+    UnknownAccount(String),
+    /// The Jira account id of the account has not been resolved yet
+    Unavailable(String),
+    /// The service answered with a body which cannot be used
+    InvalidResponse(String),
+}
+
+impl SinkError {
+    /// Whether trying again later might succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SinkError::Http(e) => e.is_transient(),
+            SinkError::UnknownAccount(_) => false,
+            SinkError::Unavailable(_) => true,
+            SinkError::InvalidResponse(_) => false,
+        }
+    }
+
+    /// Whether the worklog might have been booked despite the error
+    pub fn is_unconfirmed(&self) -> bool {
+        match self {
+            SinkError::Http(e) => e.is_unconfirmed(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for SinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SinkError::Http(e) => write!(f, "{}", e),
+            SinkError::UnknownAccount(account) => write!(f, "unknown account {}", account),
+            SinkError::Unavailable(account) => write!(
+                f,
+                "upstream unavailable, Jira has not been reachable to resolve account {}",
+                account
+            ),
+            SinkError::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+        }
+    }
+}
+
+impl Error for SinkError {}
+
+impl From<SinkError> for ApiError {
+    fn from(value: SinkError) -> Self {
+        let message = value.to_string();
+        match value {
+            SinkError::Http(e) => {
+                let kind = UpstreamKind::of(&e);
+                ApiError::new(kind.status(), kind.code(), message)
+                    .upstream(UpstreamFailure::from(&e))
+            }
+            SinkError::UnknownAccount(_) => ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "unknown_account",
+                message,
+            )
+            .field("account"),
+            SinkError::Unavailable(_) => ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "upstream_unavailable",
+                message,
+            ),
+            SinkError::InvalidResponse(_) => {
+                ApiError::new(StatusCode::BAD_GATEWAY, "upstream_error", message)
+            }
+        }
+    }
+}
+
+impl IntoResponse for SinkError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+impl From<HttpError> for SinkError {
+    fn from(value: HttpError) -> Self {
+        SinkError::Http(value)
+    }
+}
+
+impl From<reqwest::Error> for SinkError {
+    fn from(value: reqwest::Error) -> Self {
+        SinkError::Http(value.into())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmittedWorklog {
+    #[serde(alias = "tempo_worklog_id")]
+    pub worklog_id: u64,
+    pub submitted_at: DateTime<Local>,
+    pub worklog: Worklog,
+    pub payload: serde_json::Value,
+}
+
+/// A worklog as it is booked in one of the backends
+#[derive(Debug, Clone, Serialize)]
+pub struct BookedWorklog {
+    pub backend: Backend,
+    pub worklog_id: u64,
+    pub issue_id: String,
+    pub time_spent_seconds: u64,
+    pub start_date: NaiveDate,
+    pub start_time: NaiveTime,
+    pub description: Option<String>,
+}
+
+/// Durations are considered similar if they differ by at most a minute or a tenth
+fn similar_durations(a: Duration, b: Duration) -> bool {
+    let difference = a.abs_diff(b);
+    difference <= Duration::from_secs(60) || difference <= a.max(b) / 10
+}
+
+impl BookedWorklog {
+    /// Whether this is probably the given worklog or a duplicate of it
+    pub fn matches(&self, worklog: &Worklog) -> bool {
+        self.issue_id == worklog.issue_id
+            && self.start_date == worklog.start.date_naive()
+            && self.description.as_deref().unwrap_or_default()
+                == worklog.description.as_deref().unwrap_or_default()
+            && similar_durations(
+                Duration::from_secs(self.time_spent_seconds),
+                worklog.duration,
+            )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Period {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl Period {
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        self.from <= day && day <= self.to
+    }
+}
+
+/// A timesheet period of the user which is not open for new worklogs anymore
+#[derive(Debug, Clone)]
+pub struct LockedPeriod {
+    pub period: Period,
+    pub status: String,
+}
+
+/// The configured backends and which trackers are booked in which backend
+pub struct WorklogSinks {
+    tempo: Arc<TempoApi>,
+    jira: Arc<JiraWorklogs>,
+    default_backend: Backend,
+    project_backends: HashMap<String, Backend>,
+}
+
+impl WorklogSinks {
+    pub fn new(tempo: Arc<TempoApi>, jira: Arc<JiraWorklogs>, config: &AppConfig) -> Self {
+        Self {
+            tempo,
+            jira,
+            default_backend: config.worklog_backend,
+            project_backends: config.worklog_backends.clone(),
+        }
+    }
+
+    pub fn get(&self, backend: Backend) -> &dyn WorklogSink {
+        match backend {
+            Backend::Tempo => self.tempo.as_ref(),
+            Backend::Jira => self.jira.as_ref(),
+        }
+    }
+
+    /// The backend of the tracker's project, falling back to the default backend
+    pub fn backend(&self, key: &str) -> Backend {
+        let project = key.split('-').next().unwrap_or_default();
+        self.project_backends
+            .get(project)
+            .copied()
+            .unwrap_or(self.default_backend)
+    }
+
+    /// All backends trackers may be booked in
+    pub fn backends(&self) -> Vec<Backend> {
+        let mut backends = vec![self.default_backend];
+        for backend in self.project_backends.values() {
+            if !backends.contains(backend) {
+                backends.push(*backend);
+            }
+        }
+        backends
+    }
+
+    /// Fetches the booked worklogs of all backends trackers may be booked in
+    pub async fn get_worklogs(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<BookedWorklog>, SinkError> {
+        let backends = self.backends();
+        let worklogs = try_join_all(
+            backends
+                .iter()
+                .map(|backend| self.get(*backend).get_worklogs(from, to)),
+        )
+        .await?;
+        Ok(worklogs.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn durations_within_a_minute_are_similar() {
+        assert!(similar_durations(minutes(5), minutes(6)));
+        assert!(similar_durations(minutes(6), minutes(5)));
+        assert!(!similar_durations(
+            minutes(5),
+            minutes(6) + Duration::from_secs(1)
+        ));
+    }
+
+    #[test]
+    fn durations_within_a_tenth_are_similar() {
+        assert!(similar_durations(minutes(100), minutes(90)));
+        assert!(similar_durations(minutes(90), minutes(100)));
+        assert!(!similar_durations(minutes(100), minutes(89)));
+    }
+}